@@ -0,0 +1,27 @@
+//! Benchmarks the zero-timeout path of `Host::poll` and `Host::service`, which is the inner loop
+//! of servers that poll their host at a high frequency.
+//!
+//! Run with `cargo bench --features test-util --bench poll`.
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use enet::test_util::{connected_pair, localhost_host};
+
+fn bench_poll(c: &mut Criterion) {
+    let mut host = localhost_host::<()>(1);
+    c.bench_function("poll without peers", |b| {
+        b.iter(|| black_box(host.poll().unwrap().is_none()))
+    });
+    c.bench_function("service(0) without peers", |b| {
+        b.iter(|| black_box(host.service(Duration::ZERO).unwrap().is_none()))
+    });
+
+    // The peer is idle, but ENet still checks its timers on every poll.
+    let (mut server, _, _client, _) = connected_pair::<()>(0);
+    c.bench_function("poll with an idle peer", |b| {
+        b.iter(|| black_box(server.poll().unwrap().is_none()))
+    });
+}
+
+criterion_group!(benches, bench_poll);
+criterion_main!(benches);
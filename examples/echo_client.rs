@@ -0,0 +1,72 @@
+//! A client that sends a few messages to the `echo_server` example and prints the replies.
+use std::{net::Ipv4Addr, time::Duration};
+
+use anyhow::Context;
+use enet::*;
+
+const MESSAGE_COUNT: usize = 3;
+/// The disconnect code sent once all replies arrived. 0 is reserved for timeouts, see
+/// `EventType::DISCONNECT_TIMEOUT`.
+const DISCONNECT_DONE: u32 = 1;
+
+fn main() -> anyhow::Result<()> {
+    let enet = Enet::new().context("could not initialize ENet")?;
+
+    let mut host = enet
+        .create_host::<()>(
+            None,
+            1,
+            ChannelLimit::Maximum,
+            BandwidthLimit::Unlimited,
+            BandwidthLimit::Unlimited,
+        )
+        .context("could not create host")?;
+
+    let (_, server_id) = host
+        .connect(&Address::new(Ipv4Addr::LOCALHOST, 9001), 2, 0)
+        .context("connect failed")?;
+
+    let mut received = 0;
+    loop {
+        let event = match host
+            .service(Duration::from_secs(1))
+            .context("service failed")?
+        {
+            Some(event) => event,
+            None => continue,
+        };
+
+        match event.r#type() {
+            EventType::Connect { .. } => {
+                println!("connected");
+                drop(event);
+
+                let server = host.peer_mut(server_id).context("server is gone")?;
+                for i in 0..MESSAGE_COUNT {
+                    let message = format!("hello {}", i);
+                    server
+                        .send_packet(
+                            Packet::new(message.into_bytes(), PacketMode::ReliableSequenced)?,
+                            1,
+                        )
+                        .context("sending failed")?;
+                }
+            }
+            EventType::Receive { packet, .. } => {
+                println!("echo: {}", String::from_utf8_lossy(packet.data()));
+                drop(event);
+
+                received += 1;
+                if received == MESSAGE_COUNT {
+                    host.peer_mut(server_id)
+                        .context("server is gone")?
+                        .disconnect(DISCONNECT_DONE)?;
+                }
+            }
+            EventType::Disconnect { .. } => {
+                println!("disconnected");
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! A server that sends every packet it receives back to the peer it came from.
+//!
+//! Run it together with the `echo_client` example.
+use std::{net::Ipv4Addr, time::Duration};
+
+use anyhow::Context;
+use enet::*;
+
+fn main() -> anyhow::Result<()> {
+    let enet = Enet::new().context("could not initialize ENet")?;
+
+    let local_addr = Address::new(Ipv4Addr::LOCALHOST, 9001);
+
+    let mut host = enet
+        .create_host::<()>(
+            Some(&local_addr),
+            10,
+            ChannelLimit::Maximum,
+            BandwidthLimit::Unlimited,
+            BandwidthLimit::Unlimited,
+        )
+        .context("could not create host")?;
+
+    println!("listening on {:?}", host.address());
+
+    loop {
+        let mut event = match host
+            .service(Duration::from_secs(1))
+            .context("service failed")?
+        {
+            Some(event) => event,
+            None => continue,
+        };
+
+        // Copy what we need out of the event first, as the packet is borrowed from the event and
+        // we need to borrow the peer mutably to reply.
+        let reply = match event.r#type() {
+            EventType::Connect { .. } => {
+                println!("{:?} connected", event.peer_address());
+                None
+            }
+            event_type @ EventType::Disconnect { .. } => {
+                match event_type.disconnect_reason::<u32>() {
+                    Some(EventType::DISCONNECT_TIMEOUT) => {
+                        println!("{:?} timed out", event.peer_address())
+                    }
+                    Some(code) => println!("{:?} disconnected ({})", event.peer_address(), code),
+                    None => unreachable!("not a disconnect event"),
+                }
+                None
+            }
+            EventType::Receive { channel_id, packet } => {
+                Some((*channel_id, packet.data().to_vec(), packet.mode()))
+            }
+        };
+
+        if let Some((channel_id, data, mode)) = reply {
+            event
+                .peer_mut()
+                .send_packet(Packet::new(data, mode)?, channel_id)
+                .context("sending reply failed")?;
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Sends random datagrams to a server `Host` and services it, which must never panic.
+//!
+//! Half of the datagrams come from the socket of a connected client, so they pass ENet's peer
+//! lookup and exercise the parsing of commands, the other half from an unknown address.
+//!
+//! The input is split into datagrams, each prefixed by its length as a little-endian `u16`. The
+//! first bit of the length selects the sending socket.
+//!
+//! Run with `cargo fuzz run service_datagrams` from the repository root.
+
+#![no_main]
+
+use std::{
+    cell::RefCell,
+    net::{Ipv4Addr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use enet::{Address, BandwidthLimit, ChannelLimit, Enet, EventType, Host, PeerID, PeerState};
+use libfuzzer_sys::fuzz_target;
+
+struct Target {
+    server: Host<()>,
+    client: Host<()>,
+    server_id: PeerID,
+    /// Shares the socket of `client`.
+    client_socket: UdpSocket,
+    stranger_socket: UdpSocket,
+}
+
+impl Target {
+    fn new() -> Target {
+        let enet = Enet::new().unwrap();
+        let server = enet
+            .create_host(
+                Some(&Address::new(Ipv4Addr::LOCALHOST, 0)),
+                4,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+            .unwrap();
+
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let client_socket = socket.try_clone().unwrap();
+        let mut client = enet
+            .create_host_from_socket(
+                socket,
+                1,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+            .unwrap();
+        let (_, server_id) = client.connect(&server.address(), 2, 0).unwrap();
+
+        let mut target = Target {
+            server,
+            client,
+            server_id,
+            client_socket,
+            stranger_socket: UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap(),
+        };
+        target.service_until(|target| target.connected());
+        target
+    }
+
+    fn connected(&self) -> bool {
+        self.client
+            .peer(self.server_id)
+            .is_some_and(|peer| peer.state() == PeerState::Connected)
+    }
+
+    /// Services both hosts until `done` returns true, or for at most 1s.
+    fn service_until(&mut self, done: impl Fn(&Target) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while !done(self) && Instant::now() < deadline {
+            while let Some(event) = self.server.service(Duration::from_millis(1)).unwrap() {
+                handle(event.take_type());
+            }
+            while let Some(event) = self.client.service(Duration::ZERO).unwrap() {
+                handle(event.take_type());
+            }
+        }
+    }
+}
+
+/// Touches everything an application would read from an event.
+fn handle(event: EventType) {
+    if let EventType::Receive { packet, .. } = event {
+        let _ = packet
+            .data()
+            .iter()
+            .fold(0_u8, |sum, byte| sum.wrapping_add(*byte));
+        let _ = (packet.mode(), packet.flags(), packet.peek_u32_le());
+    }
+}
+
+thread_local! {
+    static TARGET: RefCell<Option<Target>> = const { RefCell::new(None) };
+}
+
+fuzz_target!(|data: &[u8]| {
+    TARGET.with(|target| {
+        let mut target = target.borrow_mut();
+        // The fuzzer may have disconnected the client, e.g. with a valid disconnect command.
+        if !target.as_ref().is_some_and(Target::connected) {
+            *target = None;
+            *target = Some(Target::new());
+        }
+        let target = target.as_mut().unwrap();
+
+        let server_address = (Ipv4Addr::LOCALHOST, target.server.address().port());
+        let expected = target.server.datagrams_received();
+        let mut sent = 0;
+        let mut data = data;
+        while data.len() >= 2 {
+            let prefix = u16::from_le_bytes([data[0], data[1]]);
+            let len = usize::from(prefix >> 1).min(data.len() - 2);
+            let datagram = &data[2..2 + len];
+            data = &data[2 + len..];
+
+            let socket = if prefix & 1 == 0 {
+                &target.client_socket
+            } else {
+                &target.stranger_socket
+            };
+            // ENet stops receiving at an empty datagram, without counting it.
+            if socket.send_to(datagram, server_address).is_ok() && !datagram.is_empty() {
+                sent += 1;
+            }
+        }
+
+        target.service_until(|target| target.server.datagrams_received() >= expected + sent);
+    });
+});
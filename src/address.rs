@@ -1,6 +1,8 @@
 use std::{
     ffi::CStr,
-    net::{Ipv4Addr, SocketAddrV4},
+    fmt::{self, Display, Formatter},
+    net::{AddrParseError, IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    str::FromStr,
 };
 
 use enet_sys::ENetAddress;
@@ -8,11 +10,113 @@ use enet_sys::ENetAddress;
 use crate::Error;
 
 /// An IPv4 address that can be used with the ENet API.
-#[derive(Clone, Debug, PartialEq, Eq)]
+///
+/// ENet only supports IPv4, so converting from a `SocketAddr` fails for IPv6 addresses, unless
+/// they are IPv4-mapped (`::ffff:a.b.c.d`).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Address {
     addr: SocketAddrV4,
 }
 
+/// An error that can occur when converting to an `Address`.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    /// The address is an IPv6 address, which ENet does not support.
+    #[error("ENet only supports IPv4 addresses, got '{}'", .0)]
+    Ipv6(SocketAddrV6),
+    /// The string is not a valid socket address.
+    #[error("invalid socket address: {}", .0)]
+    Parse(#[from] AddrParseError),
+    /// The string is not a valid subnet, see `Subnet`'s `FromStr` implementation.
+    #[error("invalid subnet '{}'", .0)]
+    InvalidSubnet(String),
+}
+
+/// A range of IPv4 addresses in CIDR notation, e.g. to match peers against a ban list.
+///
+/// ```
+/// # use enet::{Address, Subnet};
+/// let banned: Subnet = "203.0.113.0/24".parse().unwrap();
+/// let peer: Address = "203.0.113.7:9001".parse().unwrap();
+/// assert!(peer.in_subnet(&banned));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Subnet {
+    network: Ipv4Addr,
+    prefix_len: u8,
+}
+
+impl Subnet {
+    /// Creates the subnet of the addresses whose first `prefix_len` bits equal those of `ip`.
+    ///
+    /// The remaining bits of `ip` are ignored. Fails if `prefix_len` is larger than 32.
+    pub fn new(ip: Ipv4Addr, prefix_len: u8) -> Result<Subnet, AddressError> {
+        if prefix_len > 32 {
+            return Err(AddressError::InvalidSubnet(format!("{ip}/{prefix_len}")));
+        }
+
+        Ok(Subnet {
+            network: Ipv4Addr::from(u32::from(ip) & Subnet::mask(prefix_len)),
+            prefix_len,
+        })
+    }
+
+    fn mask(prefix_len: u8) -> u32 {
+        u32::MAX
+            .checked_shl(32 - u32::from(prefix_len))
+            .unwrap_or(0)
+    }
+
+    /// Returns the first address of this subnet.
+    pub fn network(&self) -> Ipv4Addr {
+        self.network
+    }
+
+    /// Returns the number of leading bits that addresses in this subnet share.
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Returns whether `ip` is part of this subnet.
+    pub fn contains(&self, ip: &Ipv4Addr) -> bool {
+        u32::from(*ip) & Subnet::mask(self.prefix_len) == u32::from(self.network)
+    }
+}
+
+/// Renders the subnet as `network/prefix_len`.
+impl Display for Subnet {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+/// Parses a subnet of the form `ip/prefix_len`, or a single `ip`, which is a subnet of one
+/// address.
+///
+/// As ENet only supports IPv4, IPv6 subnets are rejected, unless they are within the
+/// IPv4-mapped range (`::ffff:a.b.c.d/n` with `n >= 96`).
+impl FromStr for Subnet {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Subnet, AddressError> {
+        let invalid = || AddressError::InvalidSubnet(s.to_string());
+
+        let (ip, prefix_len) = match s.split_once('/') {
+            Some((ip, prefix_len)) => (ip, Some(prefix_len.parse::<u8>().map_err(|_| invalid())?)),
+            None => (s, None),
+        };
+
+        match ip.parse::<IpAddr>()? {
+            IpAddr::V4(ip) => Subnet::new(ip, prefix_len.unwrap_or(32)),
+            IpAddr::V6(ip) => match (ip.to_ipv4_mapped(), prefix_len.unwrap_or(128)) {
+                (Some(ip), prefix_len @ 96..=128) => Subnet::new(ip, prefix_len - 96),
+                _ => Err(invalid()),
+            },
+        }
+        .map_err(|_| invalid())
+    }
+}
+
 impl Address {
     /// Create a new address from an ip and a port.
     pub fn new(addr: Ipv4Addr, port: u16) -> Address {
@@ -38,10 +142,20 @@ impl Address {
     }
 
     /// Return the ip of this address
+    ///
+    /// ENet addresses are always IPv4, see `Address`.
     pub fn ip(&self) -> &Ipv4Addr {
         self.addr.ip()
     }
 
+    /// Returns whether the ip of this address is part of `subnet`.
+    ///
+    /// Combined with `Host::set_connect_validator`, this can reject connections from banned
+    /// subnets.
+    pub fn in_subnet(&self, subnet: &Subnet) -> bool {
+        subnet.contains(self.ip())
+    }
+
     /// Returns the port of this address
     pub fn port(&self) -> u16 {
         self.addr.port()
@@ -69,11 +183,56 @@ impl From<SocketAddrV4> for Address {
     }
 }
 
+impl TryFrom<SocketAddr> for Address {
+    type Error = AddressError;
+
+    fn try_from(addr: SocketAddr) -> Result<Address, AddressError> {
+        match addr {
+            SocketAddr::V4(addr) => Ok(Address::from(addr)),
+            SocketAddr::V6(addr) => match addr.ip().to_ipv4_mapped() {
+                Some(ip) => Ok(Address::new(ip, addr.port())),
+                None => Err(AddressError::Ipv6(addr)),
+            },
+        }
+    }
+}
+
+impl From<Address> for SocketAddrV4 {
+    fn from(addr: Address) -> SocketAddrV4 {
+        addr.addr
+    }
+}
+
+impl From<Address> for SocketAddr {
+    fn from(addr: Address) -> SocketAddr {
+        SocketAddr::V4(addr.addr)
+    }
+}
+
+/// Renders the address as `ip:port`.
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(&self.addr, f)
+    }
+}
+
+/// Parses an address of the form `ip:port`, see `TryFrom<SocketAddr>` for IPv6 addresses.
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Address, AddressError> {
+        Address::try_from(s.parse::<SocketAddr>()?)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::{ffi::CString, net::Ipv4Addr};
+    use std::{
+        ffi::CString,
+        net::{Ipv4Addr, SocketAddr},
+    };
 
-    use super::Address;
+    use super::{Address, AddressError, Subnet};
 
     #[test]
     fn test_from_valid_hostname() {
@@ -86,4 +245,73 @@ mod tests {
     fn test_from_invalid_hostname() {
         assert!(Address::from_hostname(&CString::new("").unwrap(), 0).is_err());
     }
+
+    #[test]
+    fn test_socket_addr_conversions() {
+        let addr = Address::new(Ipv4Addr::new(10, 0, 0, 1), 9001);
+        let socket_addr: SocketAddr = "10.0.0.1:9001".parse().unwrap();
+        assert_eq!(SocketAddr::from(addr.clone()), socket_addr);
+        assert_eq!(Address::try_from(socket_addr), Ok(addr.clone()));
+
+        let mapped: SocketAddr = "[::ffff:10.0.0.1]:9001".parse().unwrap();
+        assert_eq!(Address::try_from(mapped), Ok(addr));
+
+        let v6: SocketAddr = "[::1]:9001".parse().unwrap();
+        assert!(matches!(Address::try_from(v6), Err(AddressError::Ipv6(_))));
+    }
+
+    #[test]
+    fn test_display_from_str() {
+        let addr = Address::new(Ipv4Addr::new(192, 168, 1, 2), 1234);
+        assert_eq!(addr.to_string(), "192.168.1.2:1234");
+        assert_eq!("192.168.1.2:1234".parse::<Address>(), Ok(addr));
+
+        assert!(matches!(
+            "not an address".parse::<Address>(),
+            Err(AddressError::Parse(_))
+        ));
+        assert!(matches!(
+            "[::1]:1234".parse::<Address>(),
+            Err(AddressError::Ipv6(_))
+        ));
+    }
+
+    #[test]
+    fn test_subnet() {
+        let subnet: Subnet = "10.1.2.3/16".parse().unwrap();
+        assert_eq!(subnet.network(), Ipv4Addr::new(10, 1, 0, 0));
+        assert_eq!(subnet.prefix_len(), 16);
+        assert_eq!(subnet.to_string(), "10.1.0.0/16");
+
+        let inside = Address::new(Ipv4Addr::new(10, 1, 255, 7), 9001);
+        let outside = Address::new(Ipv4Addr::new(10, 2, 0, 1), 9001);
+        assert!(inside.in_subnet(&subnet));
+        assert!(!outside.in_subnet(&subnet));
+
+        let single: Subnet = "10.2.0.1".parse().unwrap();
+        assert!(outside.in_subnet(&single));
+        assert!(!inside.in_subnet(&single));
+        let all: Subnet = "0.0.0.0/0".parse().unwrap();
+        assert!(inside.in_subnet(&all) && outside.in_subnet(&all));
+
+        let mapped: Subnet = "::ffff:10.1.0.0/112".parse().unwrap();
+        assert_eq!(mapped, subnet);
+
+        for invalid in [
+            "10.0.0.0/33",
+            "10.0.0.0/",
+            "10.0.0.0/x",
+            "::1/128",
+            "::ffff:0:0/95",
+        ] {
+            assert!(matches!(
+                invalid.parse::<Subnet>(),
+                Err(AddressError::InvalidSubnet(s)) if s == invalid
+            ));
+        }
+        assert!(matches!(
+            "not a subnet".parse::<Subnet>(),
+            Err(AddressError::Parse(_))
+        ));
+    }
 }
@@ -0,0 +1,276 @@
+/// The size of the length prefix of every coalesced message.
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// An error that can occur when splitting coalesced messages.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum FramingError {
+    /// The data ends inside a length prefix.
+    #[error("truncated length prefix of {} bytes", .0)]
+    TruncatedPrefix(usize),
+    /// The data ends before the end of a message.
+    #[error("message of {len} bytes is truncated to {available} bytes")]
+    TruncatedMessage {
+        /// The length of the message in its prefix.
+        len: usize,
+        /// The remaining bytes of the data.
+        available: usize,
+    },
+    /// A length prefix exceeds the maximum frame length of a `FrameReader`.
+    #[error("frame of {len} bytes exceeds the maximum frame length of {max} bytes")]
+    FrameTooLarge {
+        /// The length of the frame in its prefix.
+        len: usize,
+        /// The maximum frame length.
+        max: usize,
+    },
+}
+
+/// Appends `message` to `buffer`, prefixed by its length as a little-endian `u32`.
+///
+/// This is the framing of `Peer::queue`, which `CoalescedMessages` and `FrameReader` decode. It
+/// can be used to split a stream of messages into packets of any size, e.g. to chunk a large
+/// message across several reliable packets on the same channel.
+///
+/// # Panics
+/// Panics if `message` is longer than `u32::MAX` bytes.
+pub fn write_frame(buffer: &mut Vec<u8>, message: &[u8]) {
+    let len: u32 = message
+        .len()
+        .try_into()
+        .expect("message length exceeds u32::MAX");
+    buffer.extend_from_slice(&len.to_le_bytes());
+    buffer.extend_from_slice(message);
+}
+
+/// Returns the size of `message` once it is coalesced.
+pub(crate) fn framed_len(message: &[u8]) -> usize {
+    LENGTH_PREFIX_SIZE + message.len()
+}
+
+/// An iterator over the messages of a packet sent by `Peer::flush_queued`.
+///
+/// Every message is prefixed by its length as a little-endian `u32`. If the data is malformed,
+/// the iterator returns an error once and ends.
+///
+/// ```
+/// # use enet::CoalescedMessages;
+/// let data = [2, 0, 0, 0, b'h', b'i', 0, 0, 0, 0];
+/// let messages: Vec<&[u8]> = CoalescedMessages::new(&data).collect::<Result<_, _>>().unwrap();
+/// assert_eq!(messages, [&b"hi"[..], &b""[..]]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CoalescedMessages<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> CoalescedMessages<'a> {
+    /// Creates an iterator over the messages coalesced into `data`.
+    pub fn new(data: &'a [u8]) -> CoalescedMessages<'a> {
+        CoalescedMessages { data }
+    }
+}
+
+impl<'a> Iterator for CoalescedMessages<'a> {
+    type Item = Result<&'a [u8], FramingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let data = std::mem::take(&mut self.data);
+        if data.len() < LENGTH_PREFIX_SIZE {
+            return Some(Err(FramingError::TruncatedPrefix(data.len())));
+        }
+
+        let (prefix, rest) = data.split_at(LENGTH_PREFIX_SIZE);
+        let len = u32::from_le_bytes(prefix.try_into().unwrap()) as usize;
+        if rest.len() < len {
+            return Some(Err(FramingError::TruncatedMessage {
+                len,
+                available: rest.len(),
+            }));
+        }
+
+        let (message, rest) = rest.split_at(len);
+        self.data = rest;
+        Some(Ok(message))
+    }
+}
+
+/// Reassembles frames (see `write_frame`) from a stream of received packets.
+///
+/// Unlike `CoalescedMessages`, frames may span several packets, so this can decode a stream
+/// that was split into arbitrary chunks. The packets have to be delivered in order, i.e. be sent
+/// reliably on the same channel.
+///
+/// ```
+/// # use enet::{write_frame, FrameReader};
+/// let mut stream = Vec::new();
+/// write_frame(&mut stream, b"hello");
+/// write_frame(&mut stream, b"world");
+///
+/// let mut reader = FrameReader::new();
+/// let mut frames = Vec::new();
+/// for chunk in stream.chunks(3) {
+///     reader.push(chunk);
+///     while let Some(frame) = reader.next_frame().unwrap() {
+///         frames.push(frame);
+///     }
+/// }
+/// assert_eq!(frames, [b"hello".to_vec(), b"world".to_vec()]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FrameReader {
+    buffer: Vec<u8>,
+    /// The start of the first incomplete frame in `buffer`.
+    start: usize,
+    max_frame_len: usize,
+}
+
+impl FrameReader {
+    /// Creates an empty `FrameReader` that accepts frames of any length.
+    pub fn new() -> FrameReader {
+        FrameReader::with_max_frame_len(u32::MAX as usize)
+    }
+
+    /// Creates an empty `FrameReader` that rejects frames longer than `max_frame_len` bytes.
+    ///
+    /// As the length of a frame is known from its prefix, this fails before buffering the frame,
+    /// which protects against peers announcing huge frames.
+    pub fn with_max_frame_len(max_frame_len: usize) -> FrameReader {
+        FrameReader {
+            buffer: Vec::new(),
+            start: 0,
+            max_frame_len,
+        }
+    }
+
+    /// Appends received data, e.g. the data of a `Packet`.
+    pub fn push(&mut self, data: &[u8]) {
+        // Drop the frames that were already returned.
+        if self.start > 0 {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns the next complete frame, or `None` if more data is needed.
+    ///
+    /// After an error, the stream can't be decoded anymore, as the frame boundaries are lost.
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, FramingError> {
+        let data = &self.buffer[self.start..];
+        if data.len() < LENGTH_PREFIX_SIZE {
+            return Ok(None);
+        }
+
+        let (prefix, rest) = data.split_at(LENGTH_PREFIX_SIZE);
+        let len = u32::from_le_bytes(prefix.try_into().unwrap()) as usize;
+        if len > self.max_frame_len {
+            return Err(FramingError::FrameTooLarge {
+                len,
+                max: self.max_frame_len,
+            });
+        }
+        if rest.len() < len {
+            return Ok(None);
+        }
+
+        let frame = rest[..len].to_vec();
+        self.start += LENGTH_PREFIX_SIZE + len;
+        Ok(Some(frame))
+    }
+
+    /// Returns the number of bytes that were pushed, but not returned as part of a frame yet.
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+}
+
+impl Default for FrameReader {
+    fn default() -> FrameReader {
+        FrameReader::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_frame, CoalescedMessages, FrameReader, FramingError};
+
+    #[test]
+    fn test_round_trip() {
+        let messages: [&[u8]; 3] = [b"first", b"", &[0; 300]];
+        let mut buffer = Vec::new();
+        for message in messages {
+            write_frame(&mut buffer, message);
+        }
+
+        let split: Vec<&[u8]> = CoalescedMessages::new(&buffer)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(split, messages);
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, b"ok");
+        buffer.extend_from_slice(&[1, 0]);
+
+        let mut messages = CoalescedMessages::new(&buffer);
+        assert_eq!(messages.next(), Some(Ok(&b"ok"[..])));
+        assert_eq!(messages.next(), Some(Err(FramingError::TruncatedPrefix(2))));
+        assert_eq!(messages.next(), None);
+
+        let mut messages = CoalescedMessages::new(&[5, 0, 0, 0, 1, 2]);
+        assert_eq!(
+            messages.next(),
+            Some(Err(FramingError::TruncatedMessage {
+                len: 5,
+                available: 2
+            }))
+        );
+        assert_eq!(messages.next(), None);
+    }
+
+    #[test]
+    fn test_frame_reader_chunks() {
+        let frames: [&[u8]; 4] = [b"first", b"", &[7; 300], b"last"];
+        let mut stream = Vec::new();
+        for frame in frames {
+            write_frame(&mut stream, frame);
+        }
+
+        // Splits the stream at every possible position, including inside the length prefixes.
+        for chunk_size in 1..=stream.len() {
+            let mut reader = FrameReader::new();
+            let mut read = Vec::new();
+            for chunk in stream.chunks(chunk_size) {
+                reader.push(chunk);
+                while let Some(frame) = reader.next_frame().unwrap() {
+                    read.push(frame);
+                }
+            }
+
+            assert_eq!(read, frames);
+            assert_eq!(reader.buffered_len(), 0);
+        }
+    }
+
+    #[test]
+    fn test_frame_reader_partial() {
+        let mut reader = FrameReader::with_max_frame_len(10);
+        reader.push(&[3, 0, 0, 0, 1, 2]);
+        assert_eq!(reader.next_frame(), Ok(None));
+        assert_eq!(reader.buffered_len(), 6);
+
+        reader.push(&[3, 11, 0, 0, 0]);
+        assert_eq!(reader.next_frame(), Ok(Some(vec![1, 2, 3])));
+        assert_eq!(
+            reader.next_frame(),
+            Err(FramingError::FrameTooLarge { len: 11, max: 10 })
+        );
+    }
+}
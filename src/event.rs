@@ -1,10 +1,12 @@
 #![allow(non_upper_case_globals)]
+use std::mem::ManuallyDrop;
+
 use enet_sys::{
-    ENetEvent, _ENetEventType_ENET_EVENT_TYPE_CONNECT, _ENetEventType_ENET_EVENT_TYPE_DISCONNECT,
-    _ENetEventType_ENET_EVENT_TYPE_NONE, _ENetEventType_ENET_EVENT_TYPE_RECEIVE,
+    enet_time_get, ENetEvent, _ENetEventType_ENET_EVENT_TYPE_CONNECT, _ENetEventType_ENET_EVENT_TYPE_DISCONNECT,
+    _ENetEventType_ENET_EVENT_TYPE_RECEIVE,
 };
 
-use crate::{Host, Packet, Peer, PeerID};
+use crate::{Address, Host, Packet, PacketRef, Peer, PeerID};
 
 /// This struct represents an event that can occur when servicing a `Host`.
 ///
@@ -13,25 +15,51 @@ use crate::{Host, Packet, Peer, PeerID};
 /// If you still need that data, make sure to take it out of the peer (e.g. `Peer::take_data`),
 /// before dropping the Disconnect Event.
 ///
-/// Also never run `std::mem::forget` on an Event or modify the r#type of the event, as that would
-/// skip the cleanup of the Peer.
+/// The type of an `Event` can't be modified, it can only be moved out with `Event::take_type`
+/// or `Event::take_packet`, which run the same cleanup. If an `Event` is leaked instead (e.g.
+/// with `std::mem::forget`), the cleanup runs at the start of the next `Host::service` or
+/// `Host::check_events` call.
 #[derive(Debug)]
 pub struct Event<'a, T> {
     peer: &'a mut Peer<T>,
     peer_id: PeerID,
+    peer_address: Address,
+    timestamp: u32,
+    first_receive: bool,
     r#type: EventType,
 }
 
+/// An event that does not borrow the `Host` it occurred on.
+///
+/// Created from an `Event` through `Event::into_owned`. As the `Event` is consumed in the process, a
+/// `Disconnect` event will already have cleaned up its `Peer`.
+#[derive(Debug)]
+pub struct OwnedEvent {
+    /// The `PeerID` of the peer that this event happened on.
+    pub peer_id: PeerID,
+    /// The address of the peer that this event happened on, see `Event::peer_address`.
+    pub peer_address: Address,
+    /// The ENet time at which this event was dispatched, see `Event::timestamp`.
+    pub timestamp: u32,
+    /// The type of this event.
+    pub r#type: EventType,
+}
+
 /// The type of an event.
 #[derive(Debug)]
 pub enum EventType {
     /// Peer has connected.
-    Connect,
+    Connect {
+        /// The data associated with this event. Contains the `user_data` the foreign host passed
+        /// to `Host::connect`.
+        data: u32,
+    },
     /// Peer has disconnected.
     //
     /// The data of the peer (i.e. `Peer::data`) will be dropped when the received `Event` is dropped.
     Disconnect {
-        /// The data associated with this event. Usually a reason for disconnection.
+        /// The data associated with this event. Usually a reason for disconnection, see
+        /// `EventType::disconnect_reason`.
         data: u32,
     },
     /// Peer has received a packet.
@@ -43,16 +71,58 @@ pub enum EventType {
     },
 }
 
-impl<'a, T> Event<'a, T> {
-    pub(crate) fn from_sys_event(event_sys: ENetEvent, host: &'a Host<T>) -> Option<Event<'a, T>> {
-        if event_sys.type_ == _ENetEventType_ENET_EVENT_TYPE_NONE {
-            return None;
+impl EventType {
+    /// The data of a `Disconnect` that wasn't requested by the foreign host.
+    ///
+    /// ENet uses this when the connection timed out, and when a disconnect initiated by this host
+    /// (e.g. `Peer::disconnect`) has been acknowledged. Application-defined disconnect codes
+    /// should therefore never be 0, so that a disconnect requested by the foreign host can be told
+    /// apart from a timeout.
+    pub const DISCONNECT_TIMEOUT: u32 = 0;
+
+    /// Returns the data of a `Disconnect` event converted into an application-defined reason,
+    /// None for other events.
+    ///
+    /// ```
+    /// # use enet::EventType;
+    /// #[derive(Debug, PartialEq)]
+    /// enum Reason {
+    ///     Timeout,
+    ///     Kicked,
+    ///     Other(u32),
+    /// }
+    ///
+    /// impl From<u32> for Reason {
+    ///     fn from(data: u32) -> Reason {
+    ///         match data {
+    ///             EventType::DISCONNECT_TIMEOUT => Reason::Timeout,
+    ///             1 => Reason::Kicked,
+    ///             other => Reason::Other(other),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let event = EventType::Disconnect { data: 1 };
+    /// assert_eq!(event.disconnect_reason(), Some(Reason::Kicked));
+    /// ```
+    pub fn disconnect_reason<R: From<u32>>(&self) -> Option<R> {
+        match self {
+            EventType::Disconnect { data } => Some(R::from(*data)),
+            EventType::Connect { .. } | EventType::Receive { .. } => None,
         }
+    }
+}
 
-        let peer = unsafe { Peer::new_mut(&mut *event_sys.peer) };
-        let peer_id = unsafe { host.peer_id(event_sys.peer) };
+impl<'a, T> Event<'a, T> {
+    /// Wraps an event returned by ENet.
+    ///
+    /// Returns None for `ENET_EVENT_TYPE_NONE`, and for event types this crate doesn't know, e.g.
+    /// because they were added in a newer ENet version.
+    pub(crate) fn from_sys_event(event_sys: ENetEvent, host: &'a Host<T>) -> Option<Event<'a, T>> {
         let r#type = match event_sys.type_ {
-            _ENetEventType_ENET_EVENT_TYPE_CONNECT => EventType::Connect,
+            _ENetEventType_ENET_EVENT_TYPE_CONNECT => EventType::Connect {
+                data: event_sys.data,
+            },
             _ENetEventType_ENET_EVENT_TYPE_DISCONNECT => EventType::Disconnect {
                 data: event_sys.data,
             },
@@ -60,12 +130,29 @@ impl<'a, T> Event<'a, T> {
                 channel_id: event_sys.channelID,
                 packet: Packet::from_sys_packet(event_sys.packet),
             },
-            _ => panic!("unrecognized event type: {}", event_sys.type_),
+            // `ENET_EVENT_TYPE_NONE`, or an unknown event type.
+            _ => return None,
+        };
+
+        // Safety:
+        // ENet always sets the peer of an event that is not `ENET_EVENT_TYPE_NONE` to one of the
+        // peers of the host that is borrowed for `'a`, and only sets the packet for receive events.
+        debug_assert!(!event_sys.peer.is_null(), "event without a peer");
+        let peer = unsafe { Peer::new_mut(&mut *event_sys.peer) };
+        let peer_id = unsafe { host.peer_id(event_sys.peer) };
+        let peer_address = peer.address();
+        let timestamp = unsafe { enet_time_get() };
+        let first_receive = match r#type {
+            EventType::Receive { .. } => peer.mark_received(),
+            EventType::Connect { .. } | EventType::Disconnect { .. } => false,
         };
 
         Some(Event {
             peer,
             peer_id,
+            peer_address,
+            timestamp,
+            first_receive,
             r#type,
         })
     }
@@ -80,39 +167,140 @@ impl<'a, T> Event<'a, T> {
         self.peer
     }
 
+    /// Returns the peer that this event happened on, together with an iterator over all other
+    /// peers of the `Host`, e.g. to forward a received packet to the other peers.
+    ///
+    /// While an `Event` is alive, it borrows its `Host` mutably, so `Host::peers_mut` can't be
+    /// used. The peers are separate slots of the `Host`, so lending the peer of the event and all
+    /// others at the same time is safe. Like `Host::peers_mut`, the iterator includes peers that
+    /// aren't connected, which can be skipped with `Peer::state`.
+    ///
+    /// Alternatively, convert the event with `Event::into_owned`, which ends the borrow of the
+    /// `Host`, and use `Host::peers_mut` afterwards.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use enet::*;
+    /// # fn relay(host: &mut Host<()>) -> Result<(), Error> {
+    /// if let Some(mut event) = host.service(Duration::from_millis(10))? {
+    ///     let data = event.packet().map(|packet| packet.data().to_vec());
+    ///     if let Some(data) = data {
+    ///         let (_sender, others) = event.split_peers();
+    ///         for peer in others.filter(|peer| peer.state() == PeerState::Connected) {
+    ///             let _ = peer.send_owned(data.clone(), PacketMode::ReliableSequenced, 0);
+    ///         }
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn split_peers(&mut self) -> (&mut Peer<T>, impl Iterator<Item = &mut Peer<T>>) {
+        let index = self.peer_id.index as usize;
+
+        // Safety:
+        // The host and its peers are borrowed by this event for `'a`, and the references are
+        // derived from the host's array of peers, skipping the peer that `self.peer` references,
+        // so no two references alias.
+        let (peers, count) = unsafe {
+            let host = (*self.peer.as_raw()).host;
+            ((*host).peers, (*host).peerCount)
+        };
+        let others = (0..count)
+            .filter(move |&other| other != index)
+            .map(move |other| unsafe { Peer::new_mut(&mut *peers.add(other)) });
+
+        (&mut *self.peer, others)
+    }
+
     /// The `PeerID` of the peer that this event happened on.
     pub fn peer_id(&self) -> PeerID {
         self.peer_id
     }
 
+    /// The address of the peer that this event happened on.
+    ///
+    /// The address is captured when the event is dispatched, so it stays available after the peer
+    /// of a `Disconnect` event has been cleaned up, e.g. in an `OwnedEvent`.
+    pub fn peer_address(&self) -> Address {
+        self.peer_address.clone()
+    }
+
+    /// The ENet time in milliseconds at which this event was dispatched by `Host::service` or
+    /// `Host::check_events`.
+    ///
+    /// Comparing this with `Enet::time` when the event is processed tells how long the event was
+    /// waiting in the application, as opposed to the network. The time wraps around after about
+    /// 49 days, so differences should be computed with `u32::wrapping_sub`.
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+
+    /// Returns whether this is the first `Receive` event of its peer since the peer connected.
+    ///
+    /// This allows deferring the setup of expensive per-peer state (e.g. `Peer::set_data`) until a
+    /// peer actually sends data, instead of doing it for every peer that connects and vanishes
+    /// again. Packets that were discarded by the packet filter, consumed by a channel handler (see
+    /// `Host::on_channel`) or read with `Peer::receive` don't count, as no event is
+    /// delivered for them. Always false for `Connect` and `Disconnect` events.
+    pub fn is_first_receive(&self) -> bool {
+        self.first_receive
+    }
+
     /// The type of this event.
     pub fn r#type(&self) -> &EventType {
         &self.r#type
     }
 
+    /// Returns a view of the received packet, None if this is not a Receive event.
+    ///
+    /// Unlike `take_packet`, this leaves the packet in the event.
+    pub fn packet(&self) -> Option<PacketRef<'_>> {
+        match &self.r#type {
+            EventType::Receive { packet, .. } => Some(PacketRef::from(packet)),
+            EventType::Connect { .. } | EventType::Disconnect { .. } => None,
+        }
+    }
+
     /// Take the EventType out of this event.
     /// If this peer is a Disconnect event, it will clean up the Peer.
     /// See the `Drop` implementation
-    pub fn take_type(mut self) -> EventType {
-        // Unfortunately we can't simply take the `r#type` out of the Event, as otherwise the `Drop`
-        // implementation would no longer work.
-        // We can however, swap the actual EventType with an empty EventType (in this case
-        // Connect).
-        // As the `Drop` implementation will then do nothing, we need to call cleanup_after_disconnect before we do the swap.
-        self.cleanup_after_disconnect();
+    pub fn take_type(self) -> EventType {
+        // We can't simply move the `r#type` out of the Event, as it implements `Drop`.
+        // Instead, run the cleanup of the `Drop` implementation here, and then read the type out of
+        // an event that is never dropped.
+        let mut event = ManuallyDrop::new(self);
+        event.cleanup_after_disconnect();
+
+        // Safety: `event` is never used or dropped afterwards, so the type is only owned once.
+        unsafe { std::ptr::read(&event.r#type) }
+    }
 
-        let mut r#type = EventType::Connect;
-        std::mem::swap(&mut r#type, &mut self.r#type);
-        // No need to run the drop implementation.
-        std::mem::forget(self);
+    /// Take the received packet out of this event, None if this is not a Receive event.
+    ///
+    /// See `take_type`.
+    pub fn take_packet(self) -> Option<Packet> {
+        match self.take_type() {
+            EventType::Receive { packet, .. } => Some(packet),
+            EventType::Connect { .. } | EventType::Disconnect { .. } => None,
+        }
+    }
 
-        r#type
+    /// Converts this event into an `OwnedEvent`, which no longer borrows the `Host`.
+    ///
+    /// If this is a Disconnect event, it will clean up the Peer, see `take_type`.
+    pub fn into_owned(self) -> OwnedEvent {
+        OwnedEvent {
+            peer_id: self.peer_id,
+            peer_address: self.peer_address.clone(),
+            timestamp: self.timestamp,
+            r#type: self.take_type(),
+        }
     }
 
     fn cleanup_after_disconnect(&mut self) {
         match self.r#type {
-            EventType::Disconnect { .. } => self.peer.cleanup_after_disconnect(),
-            EventType::Connect | EventType::Receive { .. } => {}
+            EventType::Disconnect { .. } => self.peer.finish_disconnect(),
+            EventType::Connect { .. } | EventType::Receive { .. } => {}
         }
     }
 }
@@ -124,3 +312,260 @@ impl<'a, T> Drop for Event<'a, T> {
         self.cleanup_after_disconnect();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, rc::Rc, time::Duration};
+
+    use enet_sys::ENetEvent;
+
+    use super::{Event, EventType};
+    use crate::{
+        test_util::{connect, service_until},
+        tests::{connected_pair, ENET},
+        BandwidthLimit, ChannelLimit, Host, Packet, PacketMode, PeerConfig, PeerID, PeerState,
+        PeerTimeout,
+    };
+
+    /// Disconnects the client and passes the resulting `Disconnect` event of the server to `f`.
+    ///
+    /// Returns the data that was associated with the client on the server.
+    fn with_disconnect_event(f: impl FnOnce(Event<'_, Rc<()>>)) -> (Host<Rc<()>>, PeerID, Rc<()>) {
+        let (mut server, client_id, mut client, server_id) = connected_pair::<Rc<()>>();
+
+        let data = Rc::new(());
+        server.peer_mut(client_id).unwrap().set_data(data.clone());
+        client.peer_mut(server_id).unwrap().disconnect(0).unwrap();
+
+        let mut f = Some(f);
+        service_until(&mut [&mut server, &mut client], |index, event| {
+            match (index, event.r#type()) {
+                (0, EventType::Disconnect { .. }) => f.take().map(|f| f(event)),
+                _ => None,
+            }
+        });
+
+        (server, client_id, data)
+    }
+
+    #[test]
+    fn test_split_peers() {
+        let (mut server, client_id, mut client, server_id) = connected_pair::<()>();
+        let mut other = ENET
+            .create_host::<()>(
+                None,
+                1,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+            .unwrap();
+        let (other_id, _) = connect(&mut server, &mut other, 0);
+
+        client
+            .peer_mut(server_id)
+            .unwrap()
+            .send_owned(b"hello".to_vec(), PacketMode::ReliableSequenced, 0)
+            .unwrap();
+        client.flush();
+
+        // The server relays the packet to all other connected peers.
+        service_until(&mut [&mut server], |_, mut event| {
+            let data = event.packet().unwrap().data().to_vec();
+            let (sender, others) = event.split_peers();
+            assert_eq!(sender.id(), client_id);
+
+            let others: Vec<_> = others
+                .filter(|peer| peer.state() == PeerState::Connected)
+                .collect();
+            assert_eq!(others.len(), 1);
+            for peer in others {
+                assert_eq!(peer.id(), other_id);
+                peer.send_owned(data.clone(), PacketMode::ReliableSequenced, 0)
+                    .unwrap();
+            }
+            Some(())
+        });
+        server.flush();
+
+        let packet = service_until(&mut [&mut other], |_, event| event.take_packet());
+        assert_eq!(packet.data(), b"hello");
+    }
+
+    #[test]
+    fn test_drop_cleans_up() {
+        let (server, client_id, data) = with_disconnect_event(|event| drop(event));
+        assert!(server.peer(client_id).is_none());
+        assert_eq!(Rc::strong_count(&data), 1);
+    }
+
+    #[test]
+    fn test_take_cleans_up() {
+        let (server, client_id, data) = with_disconnect_event(|event| {
+            assert!(matches!(event.take_type(), EventType::Disconnect { .. }))
+        });
+        assert!(server.peer(client_id).is_none());
+        assert_eq!(Rc::strong_count(&data), 1);
+
+        let (server, client_id, data) =
+            with_disconnect_event(|event| assert!(event.take_packet().is_none()));
+        assert!(server.peer(client_id).is_none());
+        assert_eq!(Rc::strong_count(&data), 1);
+    }
+
+    #[test]
+    fn test_disconnect_peer_address() {
+        with_disconnect_event(|event| {
+            let address = event.peer_address();
+            assert_eq!(address, event.peer().address());
+            assert_eq!(address.ip(), &Ipv4Addr::LOCALHOST);
+            assert_ne!(address.port(), 0);
+
+            assert_eq!(event.into_owned().peer_address, address);
+        });
+    }
+
+    #[test]
+    fn test_unknown_event_type() {
+        let (server, client_id, _client, _) = connected_pair::<()>();
+
+        let sys_event = ENetEvent {
+            type_: 99,
+            peer: server.peer(client_id).unwrap().as_raw() as *mut _,
+            channelID: 0,
+            data: 0,
+            packet: std::ptr::null_mut(),
+        };
+        assert!(Event::from_sys_event(sys_event, &server).is_none());
+    }
+
+    #[test]
+    fn test_forget_cleans_up_on_service() {
+        let (mut server, client_id, data) = with_disconnect_event(|event| std::mem::forget(event));
+        server.service(Duration::ZERO).unwrap();
+        assert!(server.peer(client_id).is_none());
+        assert_eq!(Rc::strong_count(&data), 1);
+    }
+
+    #[test]
+    fn test_timeout_disconnect_reason() {
+        let (mut server, client_id, mut client, server_id) = connected_pair::<()>();
+
+        let config = PeerConfig {
+            timeout: Some(PeerTimeout {
+                limit: 1,
+                minimum: Duration::from_millis(100),
+                maximum: Duration::from_millis(200),
+            }),
+            ping_interval: Some(Duration::from_millis(20)),
+            throttle: None,
+        };
+        server.peer_mut(client_id).unwrap().apply_config(&config);
+        // The server is not notified, so it only notices through the timeout.
+        client.peer_mut(server_id).unwrap().reset();
+
+        let reason = service_until(&mut [&mut server], |_, event| {
+            event.r#type().disconnect_reason::<u32>()
+        });
+
+        assert_eq!(reason, EventType::DISCONNECT_TIMEOUT);
+        let connect = EventType::Connect { data: 7 };
+        assert_eq!(connect.disconnect_reason::<u32>(), None);
+    }
+
+    #[test]
+    fn test_peek_packet() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+
+        client
+            .peer_mut(server_id)
+            .unwrap()
+            .send_packet(
+                Packet::new(vec![1, 2, 3], PacketMode::ReliableSequenced).unwrap(),
+                1,
+            )
+            .unwrap();
+        client.flush();
+
+        service_until(&mut [&mut server], |_, event| {
+            let packet = event.packet()?;
+            assert_eq!(packet.data(), &[1, 2, 3]);
+            assert_eq!(packet.len(), 3);
+            assert_eq!(packet.mode(), PacketMode::ReliableSequenced);
+
+            assert_eq!(event.take_packet().unwrap().data(), &[1, 2, 3]);
+            Some(())
+        });
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+
+        let before = ENET.time();
+        client
+            .peer_mut(server_id)
+            .unwrap()
+            .send_owned(vec![1], PacketMode::ReliableSequenced, 0)
+            .unwrap();
+        client.flush();
+
+        let (timestamp, elapsed, event) = service_until(&mut [&mut server], |_, event| {
+            let timestamp = event.timestamp();
+            Some((
+                timestamp,
+                ENET.time().wrapping_sub(timestamp),
+                event.into_owned(),
+            ))
+        });
+
+        assert!(timestamp.wrapping_sub(before) <= 5000);
+        assert!(elapsed <= 5000);
+        assert_eq!(event.timestamp, timestamp);
+    }
+
+    #[test]
+    fn test_is_first_receive() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+
+        for byte in [1, 2] {
+            client
+                .peer_mut(server_id)
+                .unwrap()
+                .send_owned(vec![byte], PacketMode::ReliableSequenced, 0)
+                .unwrap();
+        }
+        client.flush();
+
+        let mut first_receives = Vec::new();
+        service_until(&mut [&mut server], |_, event| {
+            let byte = event.packet().unwrap().data()[0];
+            first_receives.push((byte, event.is_first_receive()));
+            (first_receives.len() == 2).then_some(())
+        });
+        assert_eq!(first_receives, [(1, true), (2, false)]);
+    }
+
+    #[test]
+    fn test_is_first_receive_after_reset() {
+        let (mut server, client_id, mut client, server_id) = connected_pair::<()>();
+
+        // The client receives, its single slot is reused by the new connection.
+        let receive = |server: &mut Host<()>, client: &mut Host<()>, client_id| {
+            server
+                .peer_mut(client_id)
+                .unwrap()
+                .send_owned(vec![1], PacketMode::ReliableSequenced, 0)
+                .unwrap();
+            server.flush();
+            service_until(&mut [client], |_, event| {
+                event.packet().map(|_| event.is_first_receive())
+            })
+        };
+
+        assert!(receive(&mut server, &mut client, client_id));
+        client.peer_mut(server_id).unwrap().reset();
+        let (client_id, _) = connect(&mut server, &mut client, 0);
+        assert!(receive(&mut server, &mut client, client_id));
+    }
+}
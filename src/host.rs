@@ -1,12 +1,23 @@
-use std::{marker::PhantomData, mem::MaybeUninit, sync::Arc, time::Duration};
+use std::{
+    marker::PhantomData,
+    mem::MaybeUninit,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use enet_sys::{
-    enet_host_bandwidth_limit, enet_host_channel_limit, enet_host_check_events, enet_host_connect,
-    enet_host_destroy, enet_host_flush, enet_host_service, ENetEvent, ENetHost, ENetPeer,
-    ENET_PROTOCOL_MAXIMUM_CHANNEL_COUNT, enet_crc32, 
+    _ENetEventType_ENET_EVENT_TYPE_CONNECT, _ENetEventType_ENET_EVENT_TYPE_DISCONNECT,
+    _ENetEventType_ENET_EVENT_TYPE_RECEIVE, enet_crc32, enet_host_bandwidth_limit,
+    enet_host_channel_limit, enet_host_check_events, enet_host_compress,
+    enet_host_compress_with_range_coder, enet_host_connect, enet_host_destroy, enet_host_flush,
+    enet_host_service, ENetEvent, ENetHost, ENetList, ENetListNode, ENetPeer,
+    ENET_PROTOCOL_MAXIMUM_CHANNEL_COUNT, ENET_PROTOCOL_MINIMUM_CHANNEL_COUNT,
 };
 
-use crate::{Address, EnetKeepAlive, Error, Event, Peer, PeerID};
+use crate::{
+    packet::sys_packet_data, Address, ConnectError, Enet, EnetKeepAlive, Error, Event, Packet,
+    Peer, PeerConfig, PeerID, PeerState, PeerTimeout,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 /// Represents a bandwidth limit or unlimited.
@@ -53,6 +64,198 @@ impl BandwidthLimit {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// A compressor that can be enabled on a `Host` (see `Host::set_compressor`).
+///
+/// Both ends of a connection have to use the same compressor, otherwise packets will be
+/// dropped silently. To negotiate a compressor, a client can encode its compressor into the
+/// `user_data` passed to `Host::connect`, using `Compressor::encode_connect_data`. The server then
+/// reads it from the `data` of the `EventType::Connect` event, using
+/// `Compressor::decode_connect_data`, and enables the matching compressor before servicing any
+/// further.
+///
+/// The compressor is stored in the upper 8 bits of the connect data (`0` for `None`, `1` for
+/// `RangeCoder`), the lower 24 bits remain available to the application.
+///
+/// Note that the compressor applies to the whole `Host`, so a server can only serve clients that
+/// all agree on the same compressor. Clients requesting a different compressor should be
+/// disconnected.
+///
+/// # Incompressible payloads
+/// ENet doesn't compress packets, but whole datagrams, which can bundle the packets of any of
+/// the channels of a peer. Compression can therefore not be skipped for individual packets or
+/// channels. ENet does send a datagram uncompressed if compressing it didn't make it smaller, so
+/// already compressed payloads (e.g. images or audio) don't grow, they only cost the CPU time
+/// of the attempt.
+///
+/// If that time matters, send such payloads through a second connection whose hosts don't use a
+/// compressor, e.g. from a second server `Host` listening on another port.
+///
+/// ```no_run
+/// # use enet::*;
+/// # fn negotiate(server: &mut Host<()>) -> Result<(), Error> {
+/// let connect_data = match server.service(std::time::Duration::from_millis(100))? {
+///     Some(event) => match event.r#type() {
+///         EventType::Connect { data } => Some(*data),
+///         _ => None,
+///     },
+///     None => None,
+/// };
+///
+/// if let Some((compressor, _user_data)) = connect_data.and_then(Compressor::decode_connect_data) {
+///     server.set_compressor(compressor)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub enum Compressor {
+    /// Packets are not compressed (ENet default).
+    None,
+    /// Packets are compressed using ENet's builtin range coder.
+    RangeCoder,
+}
+
+impl Compressor {
+    const CONNECT_DATA_SHIFT: u32 = 24;
+    const CONNECT_DATA_USER_MASK: u32 = (1 << Compressor::CONNECT_DATA_SHIFT) - 1;
+
+    /// Encodes this compressor into the upper 8 bits of the `user_data` of a connect.
+    ///
+    /// Only the lower 24 bits of `user_data` are preserved.
+    pub fn encode_connect_data(self, user_data: u32) -> u32 {
+        let id = match self {
+            Compressor::None => 0,
+            Compressor::RangeCoder => 1,
+        };
+
+        (id << Compressor::CONNECT_DATA_SHIFT) | (user_data & Compressor::CONNECT_DATA_USER_MASK)
+    }
+
+    /// Decodes connect data created by `Compressor::encode_connect_data`.
+    ///
+    /// Returns the encoded compressor and the remaining 24 bits of user data, or `None` if the
+    /// data doesn't encode a known compressor.
+    pub fn decode_connect_data(data: u32) -> Option<(Compressor, u32)> {
+        let compressor = match data >> Compressor::CONNECT_DATA_SHIFT {
+            0 => Compressor::None,
+            1 => Compressor::RangeCoder,
+            _ => return None,
+        };
+
+        Some((compressor, data & Compressor::CONNECT_DATA_USER_MASK))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The direction of a packet passed to a capture hook (see `Host::set_capture`).
+pub enum Direction {
+    /// The packet was received from a peer.
+    Incoming,
+    /// The packet is being sent to a peer.
+    Outgoing,
+}
+
+/// Callback invoked for every packet sent or received by a `Host`.
+///
+/// Receives the direction of the packet, the `PeerID` of the remote peer, the channel id and the
+/// packet data.
+pub type CaptureHook = Box<dyn FnMut(Direction, PeerID, u8, &[u8])>;
+
+/// Callback deciding whether a packet received by a `Host` is delivered (see
+/// `Host::set_packet_filter`).
+///
+/// Receives the `PeerID` of the sending peer, the channel id and the packet data. Returning
+/// `false` discards the packet.
+pub type PacketFilter = Box<dyn FnMut(PeerID, u8, &[u8]) -> bool>;
+
+/// Callback notified about every packet a `Host` queued for sending (see
+/// `Host::set_send_accountant`).
+///
+/// Receives the `PeerID` of the receiving peer, the channel id and the length of the packet data.
+pub type SendAccountant = Box<dyn FnMut(PeerID, u8, usize)>;
+
+/// Callback handling the packets received on a channel (see `Host::on_channel`).
+///
+/// Receives the `Peer` the packet was received from, and the packet.
+pub type ChannelHandler<T> = Box<dyn FnMut(&mut Peer<T>, Packet)>;
+
+/// Callback deciding whether an incoming connection is accepted (see
+/// `Host::set_connect_validator`).
+///
+/// Receives the address of the connecting peer and the data it connected with. Returning `false`
+/// rejects the connection.
+pub type ConnectValidator = Box<dyn FnMut(Address, u32) -> bool>;
+
+/// Callback notified about peers holding a lot of received data (see
+/// `Host::set_memory_pressure_handler`).
+///
+/// Receives the `PeerID` of the peer and its `Peer::total_waiting_data`.
+pub type MemoryPressureHandler = Box<dyn FnMut(PeerID, usize)>;
+
+/// The number of events of each type a `Host` has delivered (see `Host::event_counts`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct EventCounts {
+    /// The number of `EventType::Connect` events.
+    pub connects: u64,
+    /// The number of `EventType::Disconnect` events.
+    pub disconnects: u64,
+    /// The number of `EventType::Receive` events.
+    pub receives: u64,
+}
+
+/// The outcome of `Host::close_gracefully`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseOutcome {
+    /// The queued packets were sent and the foreign host acknowledged the disconnect.
+    Closed,
+    /// The disconnect wasn't acknowledged in time, and the peer was reset.
+    TimedOut,
+}
+
+/// Host-level state that has to be reachable from the `Peer`s of a `Host`.
+///
+/// Every `Peer` of a `Host` stores a pointer to this, see `Peer::attach_hooks`.
+#[derive(Default)]
+pub(crate) struct HostHooks {
+    pub(crate) capture: Option<CaptureHook>,
+    pub(crate) filter: Option<PacketFilter>,
+    pub(crate) send_accountant: Option<SendAccountant>,
+}
+
+impl HostHooks {
+    pub(crate) fn capture(
+        &mut self,
+        direction: Direction,
+        peer_id: PeerID,
+        channel_id: u8,
+        data: &[u8],
+    ) {
+        if let Some(capture) = &mut self.capture {
+            capture(direction, peer_id, channel_id, data);
+        }
+    }
+
+    pub(crate) fn account_sent(&mut self, peer_id: PeerID, channel_id: u8, len: usize) {
+        if let Some(send_accountant) = &mut self.send_accountant {
+            send_accountant(peer_id, channel_id, len);
+        }
+    }
+
+    /// Runs the hooks for a received packet, returns whether the packet should be delivered.
+    pub(crate) fn accept_incoming(&mut self, peer_id: PeerID, channel_id: u8, data: &[u8]) -> bool {
+        self.capture(Direction::Incoming, peer_id, channel_id, data);
+
+        match &mut self.filter {
+            Some(filter) => filter(peer_id, channel_id, data),
+            None => true,
+        }
+    }
+}
+
+/// The interval in which ENet recalculates the bandwidth throttle of a host
+/// (`ENET_HOST_BANDWIDTH_THROTTLE_INTERVAL`).
+const BANDWIDTH_THROTTLE_INTERVAL: Duration = Duration::from_millis(1000);
+
 /// A `Host` represents one endpoint of an ENet connection. Created through
 /// `Enet`.
 ///
@@ -60,19 +263,171 @@ impl BandwidthLimit {
 /// transmission.
 pub struct Host<T> {
     inner: *mut ENetHost,
+    hooks: *mut HostHooks,
+    compressor: Compressor,
+    /// The peer of the last `Disconnect` event, whose cleanup may still be pending.
+    pending_disconnect: *mut ENetPeer,
+    event_counts: EventCounts,
+    datagrams_received: u64,
+    /// When the last event was dispatched, see `Host::service_adaptive`.
+    last_event: Option<Instant>,
+    default_peer_timeout: Option<PeerTimeout>,
+    channel_handlers: Vec<(u8, ChannelHandler<T>)>,
+    connect_validator: Option<ConnectValidator>,
+    memory_pressure: Option<(usize, MemoryPressureHandler)>,
     _keep_alive: Arc<EnetKeepAlive>,
     _peer_data: PhantomData<*const T>,
 }
 
 impl<T> Host<T> {
+    /// The smallest number of channels a connection can use.
+    pub const MIN_CHANNELS: usize = ENET_PROTOCOL_MINIMUM_CHANNEL_COUNT as usize;
+
+    /// The largest number of channels a connection can use, and the channel limit of a `Host`
+    /// created with `ChannelLimit::Maximum`.
+    pub const MAX_CHANNELS: usize = ENET_PROTOCOL_MAXIMUM_CHANNEL_COUNT as usize;
+
     pub(in crate) fn new(_keep_alive: Arc<EnetKeepAlive>, inner: *mut ENetHost) -> Host<T> {
         assert!(!inner.is_null());
 
-        Host {
+        let mut host = Host {
             inner,
+            hooks: Box::into_raw(Box::default()),
+            compressor: Compressor::None,
+            pending_disconnect: std::ptr::null_mut(),
+            event_counts: EventCounts::default(),
+            datagrams_received: 0,
+            last_event: None,
+            default_peer_timeout: None,
+            channel_handlers: Vec::new(),
+            connect_validator: None,
+            memory_pressure: None,
             _keep_alive,
             _peer_data: PhantomData,
+        };
+
+        let hooks = host.hooks;
+        for peer in host.peers_mut() {
+            peer.attach_hooks(hooks);
         }
+
+        host
+    }
+
+    /// Creates a `Host` from a raw ENet host, taking ownership of it.
+    ///
+    /// # Safety
+    /// `inner` has to be a valid, non-null host created by `enet_host_create`, whose peers don't
+    /// have any `data` set yet. The returned `Host` destroys it with `enet_host_destroy` when
+    /// dropped, so it must not be destroyed by anyone else.
+    pub unsafe fn from_raw(enet: &Enet, inner: *mut ENetHost) -> Host<T> {
+        Host::new(enet.keep_alive.clone(), inner)
+    }
+
+    /// Returns the raw ENet host of this `Host`.
+    ///
+    /// The host is still owned by this `Host`, so it must not be destroyed. The `data` fields of
+    /// its peers are managed by this crate and must not be modified.
+    pub fn as_raw(&self) -> *mut ENetHost {
+        self.inner
+    }
+
+    fn hooks_mut(&mut self) -> &mut HostHooks {
+        unsafe { &mut *self.hooks }
+    }
+
+    /// Sets a hook that is called for every packet sent or received on this `Host`.
+    ///
+    /// Outgoing packets are captured in `Peer::send_packet`, before they are queued.
+    /// Incoming packets are captured before they are turned into an `Event` by `Host::service` or
+    /// `Host::check_events`, or returned by `Peer::receive`.
+    ///
+    /// Replaces any previously set hook. By default, no hook is set.
+    pub fn set_capture(&mut self, capture: CaptureHook) {
+        self.hooks_mut().capture = Some(capture);
+    }
+
+    /// Removes the capture hook set with `Host::set_capture`, if any.
+    pub fn clear_capture(&mut self) {
+        self.hooks_mut().capture = None;
+    }
+
+    /// Sets a hook that is called for every packet queued for sending on this `Host`, with the
+    /// receiving peer, the channel and the length of the packet data.
+    ///
+    /// This covers `Peer::send_packet` and everything built on it, e.g. `Peer::flush_queued`, as
+    /// well as `Host::multicast`, which calls the hook once per peer. Unlike the capture hook (see
+    /// `Host::set_capture`), it only runs for packets ENet accepted, so together they account for
+    /// all traffic without counting rejected sends. When no hook is set, sending only checks for
+    /// its absence.
+    ///
+    /// Replaces any previously set hook. By default, no hook is set.
+    pub fn set_send_accountant(&mut self, send_accountant: SendAccountant) {
+        self.hooks_mut().send_accountant = Some(send_accountant);
+    }
+
+    /// Removes the hook set with `Host::set_send_accountant`, if any.
+    pub fn clear_send_accountant(&mut self) {
+        self.hooks_mut().send_accountant = None;
+    }
+
+    /// Sets a filter that decides whether a received packet is delivered.
+    ///
+    /// The filter runs on every packet received by `Host::service`, `Host::check_events` and
+    /// `Peer::receive`, after the capture hook (see `Host::set_capture`) has seen it. If it returns
+    /// `false`, the packet is destroyed right away and no `EventType::Receive` is produced for it.
+    ///
+    /// Replaces any previously set filter. By default, all packets are delivered.
+    pub fn set_packet_filter(&mut self, filter: PacketFilter) {
+        self.hooks_mut().filter = Some(filter);
+    }
+
+    /// Removes the filter set with `Host::set_packet_filter`, if any.
+    pub fn clear_packet_filter(&mut self) {
+        self.hooks_mut().filter = None;
+    }
+
+    /// Routes the packets received on `channel_id` to `handler`, instead of returning them as
+    /// events.
+    ///
+    /// The handler runs when `Host::service` or `Host::check_events` would return the
+    /// `EventType::Receive` of a packet, after the capture hook and the filter (see
+    /// `Host::set_packet_filter`) have seen it, and the servicing continues with the next event.
+    /// Handled packets aren't counted in `Host::event_counts`. Packets on channels without a
+    /// handler, and all `Connect` and `Disconnect` events, are still returned as events.
+    ///
+    /// Replaces any previously set handler of the channel.
+    pub fn on_channel(&mut self, channel_id: u8, handler: ChannelHandler<T>) {
+        self.clear_channel_handler(channel_id);
+        self.channel_handlers.push((channel_id, handler));
+    }
+
+    /// Removes the handler set with `Host::on_channel` for `channel_id`, if any.
+    pub fn clear_channel_handler(&mut self, channel_id: u8) {
+        self.channel_handlers
+            .retain(|(channel, _)| *channel != channel_id);
+    }
+
+    /// Sets a validator that decides whether an incoming connection is accepted, e.g. by checking
+    /// an authentication token in its connect data.
+    ///
+    /// The validator runs when `Host::service` or `Host::check_events` would return the
+    /// `EventType::Connect` of a peer that connected to this `Host`. If it returns `false`, the
+    /// peer is reset and no event is produced for it. Outgoing connections (see `Host::connect`)
+    /// are not validated.
+    ///
+    /// ENet only reports a connection once its handshake completed, so a rejected peer occupies
+    /// its slot until then, but not any longer. As the peer is reset, the foreign host isn't
+    /// notified and only notices the rejection by timing out.
+    ///
+    /// Replaces any previously set validator. By default, all connections are accepted.
+    pub fn set_connect_validator(&mut self, validator: ConnectValidator) {
+        self.connect_validator = Some(validator);
+    }
+
+    /// Removes the validator set with `Host::set_connect_validator`, if any.
+    pub fn clear_connect_validator(&mut self) {
+        self.connect_validator = None;
     }
 
     /// Sends any queued packets on the host specified to its designated peers.
@@ -107,6 +462,30 @@ impl<T> Host<T> {
         }
     }
 
+    /// Sets the compressor used for packets sent and received by this `Host`.
+    ///
+    /// All peers of this `Host` have to use the same compressor, see `Compressor` for how to
+    /// negotiate it.
+    pub fn set_compressor(&mut self, compressor: Compressor) -> Result<(), Error> {
+        match compressor {
+            Compressor::None => unsafe { enet_host_compress(self.inner, std::ptr::null()) },
+            Compressor::RangeCoder => {
+                let res = unsafe { enet_host_compress_with_range_coder(self.inner) };
+                if res < 0 {
+                    return Err(Error(res));
+                }
+            }
+        }
+
+        self.compressor = compressor;
+        Ok(())
+    }
+
+    /// Returns the compressor currently used by this `Host`.
+    pub fn compressor(&self) -> Compressor {
+        self.compressor
+    }
+
     /// Returns the limit of channels per connected peer for this `Host`.
     pub fn channel_limit(&self) -> ChannelLimit {
         ChannelLimit::from_enet_val(unsafe { (*self.inner).channelLimit })
@@ -127,6 +506,76 @@ impl<T> Host<T> {
         Address::from_enet_address(&unsafe { (*self.inner).address })
     }
 
+    /// Sets the timeout parameters that are applied to every new `Peer` of this `Host`, see
+    /// `Peer::set_timeout`. `None` keeps ENet's defaults.
+    ///
+    /// This only affects peers created after the call: outgoing peers when they are created by
+    /// `Host::connect` (before `PeerConfig::timeout`, which takes precedence), and incoming peers
+    /// right before their `EventType::Connect` is returned.
+    pub fn set_default_peer_timeout(&mut self, timeout: Option<PeerTimeout>) {
+        self.default_peer_timeout = timeout;
+    }
+
+    /// Returns the timeout parameters applied to new peers, see `Host::set_default_peer_timeout`.
+    pub fn default_peer_timeout(&self) -> Option<PeerTimeout> {
+        self.default_peer_timeout
+    }
+
+    /// Sets the maximum size in bytes of packets that may be sent or received by this `Host`.
+    ///
+    /// Larger incoming packets are dropped by ENet, larger outgoing packets are rejected by
+    /// `Peer::send_packet`. ENet's default is 32MiB.
+    pub fn set_maximum_packet_size(&mut self, size: usize) {
+        unsafe {
+            (*self.inner).maximumPacketSize = size;
+        }
+    }
+
+    /// Returns the maximum size of packets, see `Host::set_maximum_packet_size`.
+    pub fn maximum_packet_size(&self) -> usize {
+        unsafe { (*self.inner).maximumPacketSize }
+    }
+
+    /// Sets the maximum amount of received data in bytes that a single peer may hold before it
+    /// is delivered (see `Peer::total_waiting_data`).
+    ///
+    /// Once a peer reaches it, its further packets are dropped by ENet until the waiting data was
+    /// delivered. ENet's default is 32MiB.
+    pub fn set_maximum_waiting_data(&mut self, size: usize) {
+        unsafe {
+            (*self.inner).maximumWaitingData = size;
+        }
+    }
+
+    /// Returns the maximum waiting data per peer, see `Host::set_maximum_waiting_data`.
+    pub fn maximum_waiting_data(&self) -> usize {
+        unsafe { (*self.inner).maximumWaitingData }
+    }
+
+    /// Sets a handler that is notified about peers whose `Peer::total_waiting_data` exceeds
+    /// `threshold` bytes.
+    ///
+    /// The handler runs whenever this `Host` receives data in `Host::service` or `Host::pump`,
+    /// once for every peer above the threshold, so it is called repeatedly while a peer stays
+    /// above it and data keeps arriving. Services that receive nothing don't check the peers.
+    /// Choosing a threshold below `Host::maximum_waiting_data` gives a chance to react, e.g. by
+    /// disconnecting a peer that ties up memory with packets it never completes. As the handler
+    /// only gets the `PeerID`, act on the peer after the service returned.
+    ///
+    /// Replaces any previously set handler. By default, no handler is set.
+    pub fn set_memory_pressure_handler(
+        &mut self,
+        threshold: usize,
+        handler: MemoryPressureHandler,
+    ) {
+        self.memory_pressure = Some((threshold, handler));
+    }
+
+    /// Removes the handler set with `Host::set_memory_pressure_handler`, if any.
+    pub fn clear_memory_pressure_handler(&mut self) {
+        self.memory_pressure = None;
+    }
+
     /// Returns the number of peers allocated for this `Host`.
     pub fn peer_count(&self) -> enet_sys::size_t {
         unsafe { (*self.inner).peerCount }
@@ -160,15 +609,56 @@ impl<T> Host<T> {
         Some(peer)
     }
 
-    pub(crate) unsafe fn peer_id(&self, peer: *mut ENetPeer) -> PeerID {
-        // We can do pointer arithmetic here to determine the offset of our new Peer in the
-        // list of peers, which is it's PeerID.
-        let index = peer.offset_from((*self.inner).peers);
-        let peer = Peer::<T>::new_mut(&mut *peer);
-        PeerID {
-            index,
-            generation: peer.generation(),
+    /// Returns whether no further reliable data can currently be sent to the given peer, see
+    /// `Peer::is_send_queue_saturated`.
+    ///
+    /// Returns false if the `PeerID` is invalid.
+    pub fn is_send_queue_saturated(&self, peer: PeerID) -> bool {
+        self.peer(peer).is_some_and(Peer::is_send_queue_saturated)
+    }
+
+    /// Returns the amount of data in bytes that is queued for or in transit to any peer of this
+    /// `Host`.
+    ///
+    /// This sums `Peer::queued_outgoing_data` and `Peer::reliable_data_in_transit` across all
+    /// peers, which makes it a cheap signal for shedding non-critical traffic under load.
+    pub fn total_outgoing_queued_bytes(&self) -> usize {
+        self.peers()
+            .map(|peer| peer.queued_outgoing_data() + peer.reliable_data_in_transit() as usize)
+            .sum()
+    }
+
+    /// Sends a packet to the given peers, e.g. all peers in the same zone.
+    ///
+    /// The packet is shared between all peers instead of being copied for each of them. Invalid
+    /// `PeerID`s are skipped, as are peers the packet could not be queued for (e.g. because the
+    /// channel doesn't exist). If no peer received the packet, it is destroyed right away.
+    ///
+    /// Returns the number of peers the packet was queued for.
+    pub fn multicast(&mut self, peer_ids: &[PeerID], channel_id: u8, packet: Packet) -> usize {
+        let mut sent = 0;
+        for &peer_id in peer_ids {
+            if let Some(peer) = self.peer_mut(peer_id) {
+                if peer.send_shared(&packet, channel_id).is_ok() {
+                    sent += 1;
+                }
+            }
         }
+
+        // Dropping the packet leaves it to ENet if it was queued for any peer.
+        drop(packet);
+        sent
+    }
+
+    /// Returns the `PeerID` of a raw peer.
+    ///
+    /// `peer` has to be a non-null peer of this `Host`.
+    pub(crate) unsafe fn peer_id(&self, peer: *mut ENetPeer) -> PeerID {
+        debug_assert!(
+            (0..self.peer_count() as isize).contains(&peer.offset_from((*self.inner).peers)),
+            "peer does not belong to this host"
+        );
+        Peer::<T>::new(&*peer).id()
     }
 
     /// Returns an iterator over all peers connected to this `Host`.
@@ -203,8 +693,152 @@ impl<T> Host<T> {
         peers.iter().map(|peer| Peer::new(&*peer))
     }
 
-    fn process_event(&'_ mut self, sys_event: ENetEvent) -> Option<Event<'_, T>> {
-        Event::from_sys_event(sys_event, self)
+    /// Cleans up after the last `Disconnect` event, in case it was leaked instead of dropped.
+    ///
+    /// As an `Event` borrows its `Host`, the event can't be alive anymore at this point.
+    fn finish_pending_disconnect(&mut self) {
+        let peer = std::mem::replace(&mut self.pending_disconnect, std::ptr::null_mut());
+        if !peer.is_null() {
+            Peer::<T>::new_mut(unsafe { &mut *peer }).finish_disconnect();
+        }
+    }
+
+    /// Prepares an event for delivery, returns whether the event should be delivered.
+    ///
+    /// Receive events are passed through the hooks of this `Host`, the packet of a rejected event
+    /// is destroyed. Events of unknown types are never delivered, and neither are events without
+    /// a peer, or receive events without a packet. ENet doesn't produce the latter, but they
+    /// would make `Event` dereference null pointers, so they are skipped instead of trusted.
+    fn prepare_event(&mut self, sys_event: &ENetEvent) -> bool {
+        if sys_event.peer.is_null() {
+            return false;
+        }
+        self.last_event = Some(Instant::now());
+
+        #[allow(non_upper_case_globals)]
+        match sys_event.type_ {
+            _ENetEventType_ENET_EVENT_TYPE_CONNECT => {
+                let peer = Peer::<T>::new_mut(unsafe { &mut *sys_event.peer });
+                // Outgoing peers already got the default in `Host::connect_with`.
+                if !peer.is_outgoing() {
+                    if let Some(validator) = &mut self.connect_validator {
+                        if !validator(peer.address(), sys_event.data) {
+                            peer.reset();
+                            return false;
+                        }
+                    }
+                    if let Some(timeout) = self.default_peer_timeout {
+                        peer.set_timeout(timeout);
+                    }
+                }
+                self.event_counts.connects += 1;
+                true
+            }
+            _ENetEventType_ENET_EVENT_TYPE_DISCONNECT => {
+                Peer::<T>::new_mut(unsafe { &mut *sys_event.peer }).begin_disconnect();
+                self.pending_disconnect = sys_event.peer;
+                self.event_counts.disconnects += 1;
+                true
+            }
+            _ENetEventType_ENET_EVENT_TYPE_RECEIVE if sys_event.packet.is_null() => false,
+            _ENetEventType_ENET_EVENT_TYPE_RECEIVE => {
+                let peer_id = unsafe { self.peer_id(sys_event.peer) };
+                let data = unsafe { sys_packet_data(sys_event.packet) };
+                if self
+                    .hooks_mut()
+                    .accept_incoming(peer_id, sys_event.channelID, data)
+                {
+                    let handler = self
+                        .channel_handlers
+                        .iter_mut()
+                        .find(|(channel_id, _)| *channel_id == sys_event.channelID);
+                    return match handler {
+                        Some((_, handler)) => {
+                            let peer = Peer::new_mut(unsafe { &mut *sys_event.peer });
+                            handler(peer, Packet::from_sys_packet(sys_event.packet));
+                            false
+                        }
+                        None => {
+                            self.event_counts.receives += 1;
+                            true
+                        }
+                    };
+                }
+
+                drop(Packet::from_sys_packet(sys_event.packet));
+                false
+            }
+            // Skip event types added in newer ENet versions instead of ending the servicing.
+            _ => false,
+        }
+    }
+
+    /// Returns the number of events this `Host` has delivered since it was created.
+    ///
+    /// The counts are monotonic, reading them doesn't reset them. Packets discarded by the filter
+    /// (see `Host::set_packet_filter`), packets consumed by a channel handler (see
+    /// `Host::on_channel`) and packets returned by `Peer::receive` are not counted.
+    pub fn event_counts(&self) -> EventCounts {
+        self.event_counts
+    }
+
+    /// Returns the number of datagrams this `Host` has received since it was created, whether
+    /// they produced events or not.
+    ///
+    /// ENet silently drops datagrams it can't process, e.g. because they are malformed, fail the
+    /// checksum (see `Host::set_checksum_crc32`), come from an unknown peer or use a different
+    /// protocol version, and doesn't report why. Comparing this with `Host::event_counts`
+    /// shows whether these drops happen: acknowledgements and pings don't produce events either,
+    /// but if datagrams keep arriving while no peer connects, they are being rejected, whereas a
+    /// client that never reached this `Host` doesn't increase the count at all.
+    ///
+    /// Datagrams are received by `Host::service` and `Host::pump`. The count is monotonic.
+    pub fn datagrams_received(&self) -> u64 {
+        self.datagrams_received
+    }
+
+    /// Services this `Host` with ENet, counting the received datagrams.
+    fn sys_service(&mut self, event: *mut ENetEvent, timeout: u32) -> i32 {
+        let received_before = unsafe { (*self.inner).totalReceivedPackets };
+        let res = unsafe { enet_host_service(self.inner, event, timeout) };
+
+        // ENet's total is only 32 bits wide and wraps around eventually, so only the increase is
+        // added.
+        let received = unsafe { (*self.inner).totalReceivedPackets }.wrapping_sub(received_before);
+        self.datagrams_received += u64::from(received);
+
+        // Waiting data only grows when data is received, so idle services skip the scan.
+        if received == 0 {
+            return res;
+        }
+        if let Some((threshold, mut handler)) = self.memory_pressure.take() {
+            for peer in self.peers() {
+                if peer.total_waiting_data() > threshold {
+                    handler(peer.id(), peer.total_waiting_data());
+                }
+            }
+            self.memory_pressure = Some((threshold, handler));
+        }
+
+        res
+    }
+
+    /// Returns the maximum gap between two services of this host that doesn't delay any of ENet's
+    /// internal timers.
+    ///
+    /// This is the shortest ping interval and timeout minimum across all peers that aren't
+    /// disconnected, capped at the interval in which ENet recalculates the bandwidth throttle
+    /// (1s). Servicing less often than this delays pings and timeouts, which can make the foreign
+    /// host drop the connection.
+    pub fn recommended_service_interval(&self) -> Duration {
+        self.peers()
+            .filter(|peer| peer.state() != PeerState::Disconnected)
+            .flat_map(|peer| {
+                let peer = unsafe { &*peer.as_raw() };
+                [peer.pingInterval, peer.timeoutMinimum]
+            })
+            .map(|millis| Duration::from_millis(millis.into()))
+            .fold(BANDWIDTH_THROTTLE_INTERVAL, Duration::min)
     }
 
     /// Maintains this host and delivers an event if available.
@@ -212,20 +846,88 @@ impl<T> Host<T> {
     /// This should be called regularly for ENet to work properly with good performance.
     ///
     /// The function won't block if `timeout` is less than 1ms.
+    ///
+    /// # Event ordering
+    /// ENet keeps a queue of peers that have pending events. Each dispatched event is taken from
+    /// the peer at the front of this queue, and if that peer still has received packets pending, it
+    /// is moved to the back of the queue. Events are therefore distributed round-robin across
+    /// peers, one packet at a time, so a single chatty peer can't starve the others.
+    ///
+    /// Pending events are always dispatched before any new data is sent or received, so every call
+    /// returns at most one event, and `check_events` follows the same order.
+    ///
+    /// For a single peer, the events always occur in the order `Connect`, `Receive`s, `Disconnect`:
+    /// ENet only accepts packets from a peer once it is connected, and resets the peer when its
+    /// `Disconnect` is dispatched, so no events of that connection follow. A new connection in
+    /// the same slot has a different `PeerID`. This crate delivers the events in the order ENet
+    /// dispatches them, also in `Host::for_each_event`, `HostGroup::service` and `ServiceLoop`.
+    ///
+    /// Received packets that are still pending when a peer disconnects or times out are dropped
+    /// by ENet instead of being delivered before the `Disconnect`. When the foreign host uses
+    /// `Peer::disconnect_later`, this can't happen to its reliable packets as long as all events
+    /// are handled with `service` or `check_events`, as the disconnect is only sent after those
+    /// packets were acknowledged, and only received once all pending events were dispatched.
+    /// `Host::pump` receives data while events are pending, so it gives no such guarantee.
     pub fn service(&'_ mut self, timeout: Duration) -> Result<Option<Event<'_, T>>, Error> {
+        self.service_millis(timeout.as_millis() as u32)
+    }
+
+    /// Maintains this host and delivers an event if available, waiting longer while the host is
+    /// idle.
+    ///
+    /// While events are occurring, this waits up to `active_timeout` like `Host::service`. Once
+    /// no event was dispatched for `idle_after`, it waits up to `idle_timeout` instead, capped at
+    /// `Host::recommended_service_interval` so that idle connections are still maintained. As
+    /// `Host::service` returns as soon as an event arrives, the longer timeout doesn't delay
+    /// receiving, but packets queued in the meantime are only sent with the next service (or
+    /// `Host::flush`). A host on which no event occurred yet is idle.
+    ///
+    /// Calling this in a loop saves wakeups, and therefore CPU time and battery, while nothing
+    /// happens, e.g. on a client whose game is paused.
+    pub fn service_adaptive(
+        &'_ mut self,
+        active_timeout: Duration,
+        idle_timeout: Duration,
+        idle_after: Duration,
+    ) -> Result<Option<Event<'_, T>>, Error> {
+        let active = self
+            .last_event
+            .is_some_and(|last_event| last_event.elapsed() < idle_after);
+        let timeout = if active {
+            active_timeout
+        } else {
+            idle_timeout.min(self.recommended_service_interval())
+        };
+
+        self.service(timeout)
+    }
+
+    /// Maintains this host and delivers an event if available, without waiting for incoming data.
+    ///
+    /// This is equivalent to `host.service(Duration::ZERO)`, for hot loops that poll the host
+    /// frequently. If no event occurs, this doesn't allocate.
+    pub fn poll(&'_ mut self) -> Result<Option<Event<'_, T>>, Error> {
+        self.service_millis(0)
+    }
+
+    fn service_millis(&'_ mut self, timeout: u32) -> Result<Option<Event<'_, T>>, Error> {
+        self.finish_pending_disconnect();
+
         // ENetEvent is Copy (aka has no Drop impl), so we don't have to make sure we `mem::forget` it later on
         let mut sys_event = MaybeUninit::uninit();
 
-        let res = unsafe {
-            enet_host_service(
-                self.inner,
-                sys_event.as_mut_ptr(),
-                timeout.as_millis() as u32,
-            )
-        };
+        let res = self.sys_service(sys_event.as_mut_ptr(), timeout);
 
         match res {
-            r if r > 0 => Ok(self.process_event(unsafe { sys_event.assume_init() })),
+            r if r > 0 => {
+                let sys_event = unsafe { sys_event.assume_init() };
+                if self.prepare_event(&sys_event) {
+                    Ok(Event::from_sys_event(sys_event, self))
+                } else {
+                    // Any further events are already queued, so there is no need to service again.
+                    self.check_events()
+                }
+            }
             0 => Ok(None),
             r if r < 0 => Err(Error(r)),
             _ => panic!("unreachable"),
@@ -238,17 +940,241 @@ impl<T> Host<T> {
     /// Checks for any queued events on this `Host` and dispatches one if
     /// available
     pub fn check_events(&'_ mut self) -> Result<Option<Event<'_, T>>, Error> {
+        self.finish_pending_disconnect();
+
         // ENetEvent is Copy (aka has no Drop impl), so we don't have to make sure we
         // `mem::forget` it later on
         let mut sys_event = MaybeUninit::uninit();
 
-        let res = unsafe { enet_host_check_events(self.inner, sys_event.as_mut_ptr()) };
+        loop {
+            let res = unsafe { enet_host_check_events(self.inner, sys_event.as_mut_ptr()) };
 
-        match res {
-            r if r > 0 => Ok(self.process_event(unsafe { sys_event.assume_init() })),
-            0 => Ok(None),
-            r if r < 0 => Err(Error(r)),
-            _ => panic!("unreachable"),
+            match res {
+                r if r > 0 => {
+                    let sys_event = unsafe { sys_event.assume_init() };
+                    if self.prepare_event(&sys_event) {
+                        return Ok(Event::from_sys_event(sys_event, self));
+                    }
+                }
+                0 => return Ok(None),
+                r if r < 0 => return Err(Error(r)),
+                _ => panic!("unreachable"),
+            }
+        }
+    }
+
+    /// Maintains this host without delivering any events.
+    ///
+    /// Like `Host::service`, this sends and receives data and keeps ENet's timers running, waiting
+    /// up to `timeout` for incoming data. Unlike `Host::service`, it always uses up the whole
+    /// `timeout`, and every event that occurs is left in ENet's queue, to be delivered by
+    /// `Host::check_events` later on.
+    ///
+    /// Returns the number of events that are queued afterwards.
+    ///
+    /// ENet is not internally synchronized, and `Host` is neither `Send` nor `Sync`. So `pump` and
+    /// `check_events` can't run concurrently on different threads; this only separates I/O from
+    /// the consumption of events, e.g. pumping frequently and draining the events once per tick.
+    pub fn pump(&mut self, timeout: Duration) -> Result<usize, Error> {
+        self.finish_pending_disconnect();
+
+        let res = self.sys_service(std::ptr::null_mut(), timeout.as_millis() as u32);
+
+        if res < 0 {
+            return Err(Error(res));
+        }
+
+        Ok(self.queued_event_count())
+    }
+
+    /// Returns the number of events in ENet's dispatch queue.
+    fn queued_event_count(&self) -> usize {
+        unsafe {
+            let queue = &(*self.inner).dispatchQueue;
+            let mut count = 0;
+
+            let mut node = queue.sentinel.next as *const ENetListNode;
+            while !std::ptr::eq(node, &queue.sentinel) {
+                // `dispatchList` is the first field of a peer, so the node is the peer itself.
+                let peer = &*(node as *const ENetPeer);
+                // ENet only delivers the received packets of connected peers, and drops the
+                // peers in other states from the queue without an event.
+                count += match Peer::<T>::new(peer).state() {
+                    PeerState::ConnectionPending
+                    | PeerState::ConnectionSucceeded
+                    | PeerState::Zombie => 1,
+                    PeerState::Connected => list_len(&peer.dispatchedCommands),
+                    _ => 0,
+                };
+
+                node = (*node).next;
+            }
+
+            count
+        }
+    }
+
+    /// Maintains this host and passes all events that are available to `f`.
+    ///
+    /// This services the host once with the given `timeout`, like `Host::service`, and then
+    /// dispatches all events that were already queued, like `Host::check_events`. Every event is
+    /// dropped before the next one is dispatched, so the cleanup of `Disconnect` events happens in
+    /// order.
+    ///
+    /// As no new data is received after the first event, this returns once all queued events were
+    /// handled. Packets sent from within `f` are sent with the next service (or `Host::flush`).
+    pub fn for_each_event(
+        &mut self,
+        timeout: Duration,
+        mut f: impl FnMut(Event<'_, T>),
+    ) -> Result<(), Error> {
+        match self.service(timeout)? {
+            Some(event) => f(event),
+            None => return Ok(()),
+        }
+
+        while let Some(event) = self.check_events()? {
+            f(event);
+        }
+
+        Ok(())
+    }
+
+    /// Gracefully disconnects all peers of this `Host`.
+    ///
+    /// Every peer is disconnected with `Peer::disconnect_later`, so packets that are already
+    /// queued are still sent, and the host is serviced until all disconnects have been
+    /// acknowledged or `timeout` has elapsed. Peers that are still connected after the timeout
+    /// are reset.
+    ///
+    /// All events that occur in the meantime are dropped, which also cleans up the disconnected
+    /// peers. Returns the number of peers that had to be reset.
+    pub fn shutdown(&mut self, user_data: u32, timeout: Duration) -> Result<usize, Error> {
+        const SERVICE_INTERVAL: Duration = Duration::from_millis(10);
+
+        for peer in self.peers_mut() {
+            // Peers that are already disconnecting are simply waited for.
+            let _ = peer.disconnect_later(user_data);
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            let connected = self
+                .peers()
+                .any(|peer| peer.state() != PeerState::Disconnected);
+            if !connected || now >= deadline {
+                break;
+            }
+            self.service(SERVICE_INTERVAL.min(deadline - now))?;
+        }
+
+        let mut reset = 0;
+        for peer in self.peers_mut() {
+            if peer.state() != PeerState::Disconnected {
+                peer.reset();
+                reset += 1;
+            }
+        }
+
+        Ok(reset)
+    }
+
+    /// Gracefully disconnects a single peer, waiting up to `budget` for it to complete.
+    ///
+    /// The peer is disconnected with `Peer::disconnect_later`, and this `Host` is serviced like
+    /// with `Host::pump` until the queued packets of the peer were sent and the foreign host
+    /// acknowledged the disconnect. If that doesn't happen within `budget`, the peer is reset,
+    /// so that its slot is freed right away.
+    ///
+    /// As with `Host::pump`, no events are lost: the `EventType::Disconnect` of a peer that closed
+    /// cleanly, and any events of other peers, are delivered by the next `Host::service` or
+    /// `Host::check_events`. A peer that timed out is cleaned up immediately, without an event.
+    ///
+    /// If `peer_id` is no longer valid, the peer is already closed, and `CloseOutcome::Closed` is
+    /// returned.
+    pub fn close_gracefully(
+        &mut self,
+        peer_id: PeerID,
+        user_data: u32,
+        budget: Duration,
+    ) -> Result<CloseOutcome, Error> {
+        const PUMP_INTERVAL: Duration = Duration::from_millis(10);
+
+        match self.peer_mut(peer_id) {
+            // A peer that is already disconnecting is simply waited for.
+            Some(peer) => {
+                let _ = peer.disconnect_later(user_data);
+            }
+            None => return Ok(CloseOutcome::Closed),
+        }
+
+        let deadline = Instant::now() + budget;
+        loop {
+            // ENet keeps the peer as a zombie until its `Disconnect` event is dispatched.
+            match self.peer(peer_id).map(Peer::state) {
+                None | Some(PeerState::Zombie | PeerState::Disconnected) => {
+                    return Ok(CloseOutcome::Closed)
+                }
+                Some(_) => {}
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            self.pump(PUMP_INTERVAL.min(deadline - now))?;
+        }
+
+        let peer = self.peer_mut(peer_id).unwrap();
+        peer.reset();
+
+        Ok(CloseOutcome::TimedOut)
+    }
+
+    /// Measures the round trip time to a connected peer, waiting up to `timeout` for it.
+    ///
+    /// A ping is sent with `Peer::ping`, and this `Host` is serviced like with `Host::pump` until
+    /// the foreign host acknowledged it. Returns the time from sending the ping until the
+    /// acknowledgement was received, or `None` if the peer is not connected or didn't answer
+    /// within `timeout`. Afterwards, `Peer::mean_rtt` includes the measured round trip too.
+    ///
+    /// As with `Host::pump`, no events are lost, they are delivered by the next `Host::service`
+    /// or `Host::check_events`.
+    ///
+    /// To pick the closest of several servers, e.g. for matchmaking, connect to all candidates,
+    /// then call this for each of them and keep the one with the smallest round trip. For probes
+    /// that shouldn't block on each other, call `Peer::ping` on all peers first, and check
+    /// `Peer::ping_acknowledged` while servicing as usual.
+    pub fn measure_rtt(
+        &mut self,
+        peer_id: PeerID,
+        timeout: Duration,
+    ) -> Result<Option<Duration>, Error> {
+        // Finer than the other helpers, as the interval limits the precision of the measurement.
+        const PUMP_INTERVAL: Duration = Duration::from_millis(1);
+
+        let ping = match self.peer_mut(peer_id).and_then(Peer::ping) {
+            Some(ping) => ping,
+            None => return Ok(None),
+        };
+        self.flush();
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.peer(peer_id) {
+                Some(peer) if peer.ping_acknowledged(&ping) => {
+                    return Ok(Some(ping.sent().elapsed()))
+                }
+                Some(peer) if peer.state() == PeerState::Connected => {}
+                _ => return Ok(None),
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.pump(PUMP_INTERVAL.min(deadline - now))?;
         }
     }
 
@@ -259,12 +1185,35 @@ impl<T> Host<T> {
     ///
     /// `channel_count` specifies how many channels to allocate for this peer.
     /// `user_data` is a user-specified value that can be chosen arbitrarily.
+    ///
+    /// A `channel_count` outside of `Host::MIN_CHANNELS..=Host::MAX_CHANNELS` is rejected with
+    /// `ConnectError::InvalidChannelCount`, instead of being clamped by ENet. The foreign host
+    /// may still lower the channel count to its own channel limit during the handshake.
     pub fn connect(
         &mut self,
         address: &Address,
         channel_count: enet_sys::size_t,
         user_data: u32,
-    ) -> Result<(&mut Peer<T>, PeerID), Error> {
+    ) -> Result<(&mut Peer<T>, PeerID), ConnectError> {
+        self.connect_with(address, channel_count, user_data, &PeerConfig::default())
+    }
+
+    /// Initiates a connection to a foreign host, like `Host::connect`, and applies `config` to the
+    /// new peer.
+    ///
+    /// The config is applied before the connection request is sent, so it is already in effect
+    /// during the handshake (e.g. a longer timeout for slow links).
+    pub fn connect_with(
+        &mut self,
+        address: &Address,
+        channel_count: enet_sys::size_t,
+        user_data: u32,
+        config: &PeerConfig,
+    ) -> Result<(&mut Peer<T>, PeerID), ConnectError> {
+        if !(Self::MIN_CHANNELS..=Self::MAX_CHANNELS).contains(&channel_count) {
+            return Err(ConnectError::InvalidChannelCount(channel_count));
+        }
+
         let res: *mut ENetPeer = unsafe {
             enet_host_connect(
                 self.inner,
@@ -275,13 +1224,73 @@ impl<T> Host<T> {
         };
 
         if res.is_null() {
-            return Err(Error(0));
+            return Err(Error(0).into());
+        }
+
+        let peer_id = unsafe { self.peer_id(res) };
+        let peer = Peer::new_mut(unsafe { &mut *res });
+        peer.mark_outgoing();
+        if let Some(timeout) = self.default_peer_timeout {
+            peer.set_timeout(timeout);
         }
+        peer.apply_config(config);
 
-        Ok((Peer::new_mut(unsafe { &mut *res }), unsafe {
-            self.peer_id(res)
-        }))
+        Ok((peer, peer_id))
     }
+
+    /// Connects to the first of `addresses` that accepts the connection.
+    ///
+    /// The addresses are tried in order. Each attempt services this `Host` until the foreign host
+    /// accepted the connection, or until `attempt_timeout` elapsed, in which case the peer of the
+    /// attempt is reset and the next address is tried.
+    ///
+    /// While connecting, this `Host` is serviced like with `Host::pump`, so no events are lost:
+    /// the `EventType::Connect` of the returned peer, and any events of other peers, are delivered
+    /// by the next `Host::service` or `Host::check_events`.
+    pub fn connect_any(
+        &mut self,
+        addresses: &[Address],
+        channel_count: enet_sys::size_t,
+        user_data: u32,
+        attempt_timeout: Duration,
+    ) -> Result<(&mut Peer<T>, PeerID), ConnectError> {
+        const PUMP_INTERVAL: Duration = Duration::from_millis(10);
+
+        if addresses.is_empty() {
+            return Err(ConnectError::NoAddresses);
+        }
+
+        for address in addresses {
+            let (_, peer_id) = self.connect(address, channel_count, user_data)?;
+
+            let deadline = Instant::now() + attempt_timeout;
+            let connected = loop {
+                match self.peer(peer_id).map(Peer::state) {
+                    Some(PeerState::ConnectionSucceeded | PeerState::Connected) => break true,
+                    Some(PeerState::Connecting | PeerState::AcknowledgingConnect) => {}
+                    // The connection was refused.
+                    _ => break false,
+                }
+
+                let now = Instant::now();
+                if now >= deadline {
+                    break false;
+                }
+                self.pump(PUMP_INTERVAL.min(deadline - now))?;
+            };
+
+            if connected {
+                return Ok((self.peer_mut(peer_id).unwrap(), peer_id));
+            }
+
+            if let Some(peer) = self.peer_mut(peer_id) {
+                peer.reset();
+            }
+        }
+
+        Err(ConnectError::Failed)
+    }
+
     /// Set the checksum callback to enet_crc32
     pub fn set_checksum_crc32(&self) {
         unsafe {
@@ -291,6 +1300,31 @@ impl<T> Host<T> {
 
 }
 
+/// Returns the number of nodes in an ENet list.
+///
+/// `list` has to be an initialized list.
+unsafe fn list_len(list: &ENetList) -> usize {
+    let mut len = 0;
+
+    let mut node = list.sentinel.next as *const ENetListNode;
+    while !std::ptr::eq(node, &list.sentinel) {
+        len += 1;
+        node = (*node).next;
+    }
+
+    len
+}
+
+#[cfg(unix)]
+impl<T> std::os::unix::io::AsRawFd for Host<T> {
+    /// Returns the file descriptor of the socket of this `Host`.
+    ///
+    /// The socket is still owned by the `Host`, and must not be closed.
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        unsafe { (*self.inner).socket }
+    }
+}
+
 impl<T> Drop for Host<T> {
     /// Call the corresponding ENet cleanup-function(s).
     fn drop(&mut self) {
@@ -300,6 +1334,1091 @@ impl<T> Drop for Host<T> {
 
         unsafe {
             enet_host_destroy(self.inner);
+            drop(Box::from_raw(self.hooks));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        net::Ipv4Addr,
+        panic::{self, AssertUnwindSafe},
+        rc::Rc,
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        test_util::{connect, localhost_host, service_until, wait_for},
+        tests::{allocations, connected_pair, ENET},
+        Address, BandwidthLimit, ChannelLimit, CloseOutcome, Compressor, ConnectError, Direction,
+        EventCounts, EventType, Host, Packet, PacketMode, PeerConfig, PeerID, PeerState,
+        PeerTimeout,
+    };
+
+    /// Services `host` until it receives a packet, returns the packet data.
+    fn receive_data(host: &mut Host<()>) -> Vec<u8> {
+        service_until(&mut [host], |_, event| event.take_packet())
+            .data()
+            .to_vec()
+    }
+
+    /// Returns a generator of a fixed xorshift sequence, so tests with random data are
+    /// reproducible.
+    fn xorshift() -> impl FnMut() -> u32 {
+        let mut state = 0x2545_f491_u32;
+        move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        }
+    }
+
+    fn create_host(address: Option<&Address>) -> Host<()> {
+        ENET.create_host(
+            address,
+            1,
+            ChannelLimit::Maximum,
+            BandwidthLimit::Unlimited,
+            BandwidthLimit::Unlimited,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compressor_connect_data() {
+        let data = Compressor::RangeCoder.encode_connect_data(0x1234);
+        assert_eq!(
+            Compressor::decode_connect_data(data),
+            Some((Compressor::RangeCoder, 0x1234))
+        );
+        assert_eq!(
+            Compressor::decode_connect_data(Compressor::None.encode_connect_data(0xFFFF_FFFF)),
+            Some((Compressor::None, 0x00FF_FFFF))
+        );
+        assert_eq!(Compressor::decode_connect_data(0xFF00_0000), None);
+    }
+
+    #[test]
+    fn test_capture() {
+        let (mut server, client_id, mut client, server_id) = connected_pair::<()>();
+
+        let captured = Rc::new(RefCell::new(Vec::new()));
+        let server_captured = captured.clone();
+        server.set_capture(Box::new(move |direction, peer_id, channel_id, data| {
+            server_captured
+                .borrow_mut()
+                .push((direction, peer_id, channel_id, data.to_vec()))
+        }));
+        let client_captured = captured.clone();
+        client.set_capture(Box::new(move |direction, peer_id, channel_id, data| {
+            client_captured
+                .borrow_mut()
+                .push((direction, peer_id, channel_id, data.to_vec()))
+        }));
+
+        client
+            .peer_mut(server_id)
+            .unwrap()
+            .send_packet(
+                Packet::new(b"hello".to_vec(), PacketMode::ReliableSequenced).unwrap(),
+                1,
+            )
+            .unwrap();
+        client.flush();
+
+        receive_data(&mut server);
+
+        assert_eq!(
+            *captured.borrow(),
+            vec![
+                (Direction::Outgoing, server_id, 1, b"hello".to_vec()),
+                (Direction::Incoming, client_id, 1, b"hello".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_send_accountant() {
+        let (_server, _, mut client, server_id) = connected_pair::<()>();
+
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let client_sent = sent.clone();
+        client.set_send_accountant(Box::new(move |peer_id, channel_id, len| {
+            client_sent.borrow_mut().push((peer_id, channel_id, len))
+        }));
+
+        let peer = client.peer_mut(server_id).unwrap();
+        peer.send_owned(b"hello".to_vec(), PacketMode::ReliableSequenced, 1)
+            .unwrap();
+        // Sends on channels that don't exist are rejected by ENet, and not accounted.
+        assert!(peer
+            .send_owned(b"lost".to_vec(), PacketMode::ReliableSequenced, 200)
+            .is_err());
+        let packet = Packet::new(vec![0; 3], PacketMode::UnreliableSequenced).unwrap();
+        assert_eq!(client.multicast(&[server_id], 0, packet), 1);
+
+        client.clear_send_accountant();
+        client
+            .peer_mut(server_id)
+            .unwrap()
+            .send_owned(b"unaccounted".to_vec(), PacketMode::ReliableSequenced, 0)
+            .unwrap();
+
+        assert_eq!(*sent.borrow(), vec![(server_id, 1, 5), (server_id, 0, 3)]);
+    }
+
+    #[test]
+    fn test_send_accountant_panic() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+        client.set_send_accountant(Box::new(|_, _, _| panic!("accountant failed")));
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            client.peer_mut(server_id).unwrap().send_owned(
+                b"hello".to_vec(),
+                PacketMode::ReliableSequenced,
+                0,
+            )
+        }));
+        assert!(res.is_err());
+
+        // The packet was queued before the accountant panicked, so it is still sent.
+        client.flush();
+        assert_eq!(receive_data(&mut server), b"hello");
+    }
+
+    #[test]
+    fn test_multicast_capture_panic() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+        let mut captured = 0;
+        client.set_capture(Box::new(move |_, _, _, _| {
+            captured += 1;
+            assert!(captured < 2, "capture failed");
+        }));
+
+        // The packet is queued once before the capture hook panics for the second send.
+        let packet = Packet::new(b"hello".to_vec(), PacketMode::ReliableSequenced).unwrap();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            client.multicast(&[server_id, server_id], 0, packet)
+        }));
+        assert!(res.is_err());
+
+        client.clear_capture();
+        client.flush();
+        assert_eq!(receive_data(&mut server), b"hello");
+    }
+
+    #[test]
+    fn test_packet_filter() {
+        let (mut server, client_id, mut client, server_id) = connected_pair::<()>();
+
+        let captured = Rc::new(RefCell::new(0));
+        let server_captured = captured.clone();
+        server.set_capture(Box::new(move |_, _, _, _| {
+            *server_captured.borrow_mut() += 1
+        }));
+        server.set_packet_filter(Box::new(move |peer_id, _, data| {
+            assert_eq!(peer_id, client_id);
+            !data.starts_with(b"banned")
+        }));
+
+        let peer = client.peer_mut(server_id).unwrap();
+        for data in [&b"banned"[..], b"hello"] {
+            peer.send_packet(
+                Packet::new(data.to_vec(), PacketMode::ReliableSequenced).unwrap(),
+                1,
+            )
+            .unwrap();
+        }
+        client.flush();
+
+        assert_eq!(receive_data(&mut server), b"hello");
+        assert_eq!(*captured.borrow(), 2);
+    }
+
+    #[test]
+    fn test_for_each_event() {
+        let (mut server, client_id, mut client, server_id) = connected_pair::<()>();
+
+        let peer = client.peer_mut(server_id).unwrap();
+        for data in [&b"first"[..], b"second", b"third"] {
+            peer.send_packet(
+                Packet::new(data.to_vec(), PacketMode::ReliableSequenced).unwrap(),
+                1,
+            )
+            .unwrap();
+        }
+        client.flush();
+
+        let mut received = Vec::new();
+        wait_for("receiving", || {
+            server
+                .for_each_event(Duration::from_millis(10), |event| {
+                    assert_eq!(event.peer_id(), client_id);
+                    if let Some(packet) = event.take_packet() {
+                        received.push(packet.data().to_vec());
+                    }
+                })
+                .unwrap();
+            (received.len() == 3).then_some(())
+        });
+
+        assert_eq!(
+            received,
+            vec![b"first".to_vec(), b"second".to_vec(), b"third".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_event_counts() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+
+        server.set_packet_filter(Box::new(|_, _, data| !data.is_empty()));
+
+        let peer = client.peer_mut(server_id).unwrap();
+        for data in [vec![], vec![1]] {
+            peer.send_packet(Packet::new(data, PacketMode::ReliableSequenced).unwrap(), 1)
+                .unwrap();
+        }
+        client.flush();
+
+        wait_for("receiving", || {
+            server.service(Duration::from_millis(10)).unwrap();
+            (server.event_counts().receives > 0).then_some(())
+        });
+
+        assert_eq!(
+            server.event_counts(),
+            EventCounts {
+                connects: 1,
+                disconnects: 0,
+                receives: 1,
+            }
+        );
+        assert_eq!(client.event_counts().connects, 1);
+    }
+
+    #[test]
+    fn test_service_adaptive() {
+        let (mut server, client_id, _client, _) = connected_pair::<()>();
+        server
+            .peer_mut(client_id)
+            .unwrap()
+            .set_ping_interval(Duration::from_millis(100));
+        let service = |server: &mut Host<()>| {
+            let start = Instant::now();
+            let event = server
+                .service_adaptive(
+                    Duration::from_millis(1),
+                    Duration::from_secs(10),
+                    Duration::from_millis(200),
+                )
+                .unwrap();
+            assert!(event.is_none());
+            start.elapsed()
+        };
+
+        // The `Connect` event was just dispatched, so the host is active.
+        assert!(service(&mut server) < Duration::from_millis(50));
+
+        // Idle, but the ping interval caps the timeout.
+        thread::sleep(Duration::from_millis(200));
+        let elapsed = service(&mut server);
+        assert!(elapsed >= Duration::from_millis(90));
+        assert!(elapsed < Duration::from_secs(1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_random_datagrams() {
+        let mut server = localhost_host::<()>(2);
+        let server_address = (Ipv4Addr::LOCALHOST, server.address().port());
+
+        // The datagrams are sent from the socket of the client, so they pass ENet's peer lookup
+        // and reach the parsing of commands.
+        let socket = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let client_socket = socket.try_clone().unwrap();
+        let mut client = ENET
+            .create_host_from_socket::<()>(
+                socket,
+                1,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+            .unwrap();
+        let (client_id, server_id) = connect(&mut server, &mut client, 0);
+        let session_id = unsafe { (*server.peer(client_id).unwrap().as_raw()).incomingSessionID };
+
+        let mut random = xorshift();
+
+        let received_before = server.datagrams_received();
+        for _ in 0..200 {
+            let len = random() as usize % 1400;
+            let mut datagram: Vec<u8> = (0..len).map(|_| random() as u8).collect();
+            // Address the datagram to the client's slot and session, without the compression
+            // flag, so ENet parses its commands instead of rejecting the header.
+            if len >= 2 {
+                let header = (client_id.index as u16)
+                    | (u16::from(session_id) << 12)
+                    | (random() as u16 & 0x8000);
+                datagram[..2].copy_from_slice(&header.to_be_bytes());
+            }
+            client_socket.send_to(&datagram, server_address).unwrap();
+        }
+
+        // The garbage may well produce events, e.g. disconnect the client, but every event has to
+        // be safe to handle.
+        wait_for("receiving", || {
+            if let Some(event) = server.service(Duration::from_millis(10)).unwrap() {
+                if let Some(packet) = event.packet() {
+                    let _ = (packet.data().len(), packet.flags());
+                }
+            }
+            (server.datagrams_received() >= received_before + 200).then_some(())
+        });
+
+        // The server still accepts connections afterwards.
+        client.peer_mut(server_id).unwrap().reset();
+        let (client_id, server_id) = connect(&mut server, &mut client, 0);
+        client
+            .peer_mut(server_id)
+            .unwrap()
+            .send_owned(b"still here".to_vec(), PacketMode::ReliableSequenced, 0)
+            .unwrap();
+        client.flush();
+        let packet = service_until(&mut [&mut server], |_, event| {
+            (event.peer_id() == client_id).then(|| event.take_packet())?
+        });
+        assert_eq!(packet.data(), b"still here");
+    }
+
+    #[test]
+    fn test_memory_pressure_handler() {
+        let (mut server, client_id, mut client, server_id) = connected_pair::<()>();
+        assert_eq!(server.maximum_waiting_data(), 32 * 1024 * 1024);
+        server.set_maximum_waiting_data(4000);
+
+        let notified = Rc::new(RefCell::new(Vec::new()));
+        let handler_notified = notified.clone();
+        server.set_memory_pressure_handler(
+            1000,
+            Box::new(move |peer_id, len| handler_notified.borrow_mut().push((peer_id, len))),
+        );
+
+        let peer = client.peer_mut(server_id).unwrap();
+        for _ in 0..8 {
+            peer.send_owned(vec![0; 600], PacketMode::ReliableSequenced, 0)
+                .unwrap();
         }
+        client.flush();
+
+        // Pumping leaves the packets waiting. ENet accepts packets until the maximum is reached,
+        // so the 7th packet still fits, and the 8th is dropped.
+        wait_for("receiving", || {
+            client.service(Duration::ZERO).unwrap();
+            server.pump(Duration::from_millis(10)).unwrap();
+            (server.peer(client_id).unwrap().total_waiting_data() >= 4200).then_some(())
+        });
+        client.service(Duration::ZERO).unwrap();
+        server.pump(Duration::from_millis(10)).unwrap();
+        assert_eq!(server.peer(client_id).unwrap().total_waiting_data(), 4200);
+
+        let (peer_id, len) = *notified.borrow().last().unwrap();
+        assert_eq!(peer_id, client_id);
+        assert!(len > 1000);
+
+        // Servicing without receiving any data doesn't call the handler.
+        notified.borrow_mut().clear();
+        server.pump(Duration::ZERO).unwrap();
+        assert!(notified.borrow().is_empty());
+
+        // Delivering the packets releases the data.
+        while server.check_events().unwrap().is_some() {}
+        assert_eq!(server.peer(client_id).unwrap().total_waiting_data(), 0);
+        notified.borrow_mut().clear();
+        server.service(Duration::from_millis(10)).unwrap();
+        assert!(notified.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_datagrams_received() {
+        let mut server = create_host(Some(&Address::new(Ipv4Addr::LOCALHOST, 0)));
+        server.service(Duration::from_millis(10)).unwrap();
+        assert_eq!(server.datagrams_received(), 0);
+
+        // Datagrams that aren't ENet's protocol are dropped without an event.
+        let socket = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        for _ in 0..3 {
+            socket
+                .send_to(b"garbage", (Ipv4Addr::LOCALHOST, server.address().port()))
+                .unwrap();
+        }
+        wait_for("receiving", || {
+            assert!(server.service(Duration::from_millis(10)).unwrap().is_none());
+            (server.datagrams_received() >= 3).then_some(())
+        });
+        server.pump(Duration::from_millis(10)).unwrap();
+
+        assert_eq!(server.datagrams_received(), 3);
+        assert_eq!(server.event_counts(), EventCounts::default());
+    }
+
+    #[test]
+    fn test_pump() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+
+        let peer = client.peer_mut(server_id).unwrap();
+        for data in [vec![1], vec![2], vec![3]] {
+            peer.send_owned(data, PacketMode::ReliableSequenced, 1)
+                .unwrap();
+        }
+        client.flush();
+
+        wait_for("receiving", || {
+            (server.pump(Duration::from_millis(10)).unwrap() >= 3).then_some(())
+        });
+
+        let mut received = Vec::new();
+        while let Some(event) = server.check_events().unwrap() {
+            received.push(event.take_packet().unwrap().data().to_vec());
+        }
+        assert_eq!(received, vec![vec![1], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn test_pump_disconnect_later() {
+        let (mut server, client_id, mut client, server_id) = connected_pair::<()>();
+
+        let peer = client.peer_mut(server_id).unwrap();
+        for data in [vec![1], vec![2]] {
+            peer.send_owned(data, PacketMode::ReliableSequenced, 1)
+                .unwrap();
+        }
+        client.flush();
+
+        wait_for("receiving", || {
+            (server.pump(Duration::from_millis(10)).unwrap() >= 2).then_some(())
+        });
+
+        // With an outgoing packet, the peer stays in `DisconnectLater` until the client
+        // acknowledges it, and its undelivered packets are never dispatched.
+        let peer = server.peer_mut(client_id).unwrap();
+        peer.send_owned(vec![3], PacketMode::ReliableSequenced, 0)
+            .unwrap();
+        peer.disconnect_later(0).unwrap();
+        assert_eq!(peer.state(), PeerState::DisconnectLater);
+
+        assert_eq!(server.pump(Duration::ZERO).unwrap(), 0);
+        assert!(server.check_events().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_total_outgoing_queued_bytes() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+        assert_eq!(client.total_outgoing_queued_bytes(), 0);
+
+        let peer = client.peer_mut(server_id).unwrap();
+        peer.send_owned(vec![0; 100], PacketMode::ReliableSequenced, 0)
+            .unwrap();
+        peer.send_owned(vec![0; 50], PacketMode::UnreliableSequenced, 1)
+            .unwrap();
+        assert_eq!(peer.queued_outgoing_data(), 150);
+        assert_eq!(client.total_outgoing_queued_bytes(), 150);
+
+        // Once sent, the reliable packet stays in transit until it has been acknowledged.
+        client.flush();
+        let peer = client.peer(server_id).unwrap();
+        assert_eq!(peer.queued_outgoing_data(), 0);
+        assert_eq!(peer.reliable_data_in_transit(), 100);
+        assert_eq!(client.total_outgoing_queued_bytes(), 100);
+
+        wait_for("acknowledgement", || {
+            server.service(Duration::from_millis(10)).unwrap();
+            client.service(Duration::from_millis(10)).unwrap();
+            (client.total_outgoing_queued_bytes() == 0).then_some(())
+        });
+    }
+
+    #[test]
+    fn test_multicast() {
+        let (mut server, first_id, mut first, _) = connected_pair::<()>();
+
+        let mut second = create_host(None);
+        let (second_id, _) = connect(&mut server, &mut second, 0);
+
+        let invalid = PeerID {
+            index: first_id.index,
+            generation: first_id.generation + 1,
+        };
+        let packet = Packet::new(vec![1, 2, 3], PacketMode::ReliableSequenced).unwrap();
+        assert_eq!(
+            server.multicast(&[first_id, invalid, second_id], 0, packet),
+            2
+        );
+        let packet = Packet::new(vec![4], PacketMode::ReliableSequenced).unwrap();
+        assert_eq!(server.multicast(&[invalid], 0, packet), 0);
+        server.flush();
+
+        for client in [&mut first, &mut second] {
+            assert_eq!(receive_data(client), [1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn test_shutdown_resets_unresponsive_peers() {
+        let (mut server, client_id, _client, _) = connected_pair::<Rc<()>>();
+
+        let data = Rc::new(());
+        server.peer_mut(client_id).unwrap().set_data(data.clone());
+
+        // The client is never serviced, so it can't acknowledge the disconnect.
+        assert_eq!(server.shutdown(1, Duration::from_millis(100)).unwrap(), 1);
+        assert!(server.peer(client_id).is_none());
+        assert_eq!(Rc::strong_count(&data), 1);
+    }
+
+    #[test]
+    fn test_recommended_service_interval() {
+        let (mut server, client_id, _client, _) = connected_pair::<()>();
+        assert_eq!(
+            create_host(None).recommended_service_interval(),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            server.recommended_service_interval(),
+            Duration::from_millis(500)
+        );
+
+        let peer = server.peer_mut(client_id).unwrap();
+        peer.set_ping_interval(Duration::from_secs(2));
+        peer.set_timeout(PeerTimeout {
+            limit: 32,
+            minimum: Duration::from_millis(200),
+            maximum: Duration::from_secs(30),
+        });
+        assert_eq!(
+            server.recommended_service_interval(),
+            Duration::from_millis(200)
+        );
+    }
+
+    #[test]
+    fn test_connect_any() {
+        let (port_sender, port_receiver) = mpsc::channel();
+        let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+
+        // The server has to be serviced while the client connects.
+        let server_thread = thread::spawn(move || {
+            let mut server = create_host(Some(&Address::new(Ipv4Addr::LOCALHOST, 0)));
+            port_sender.send(server.address().port()).unwrap();
+
+            while stop_receiver.try_recv().is_err() {
+                server.service(Duration::from_millis(10)).unwrap();
+            }
+        });
+
+        // Nothing listens on the port of a destroyed host.
+        let unused = create_host(Some(&Address::new(Ipv4Addr::LOCALHOST, 0))).address();
+        let server = Address::new(Ipv4Addr::LOCALHOST, port_receiver.recv().unwrap());
+
+        let mut client = create_host(None);
+        assert!(matches!(
+            client.connect_any(&[], 1, 0, Duration::from_millis(100)),
+            Err(ConnectError::NoAddresses)
+        ));
+
+        let (peer, _) = client
+            .connect_any(&[unused, server.clone()], 1, 0, Duration::from_millis(200))
+            .unwrap();
+        assert_eq!(peer.address(), server);
+
+        // The connect event is still delivered, and the failed attempt left no peer behind.
+        let event = client.check_events().unwrap().unwrap();
+        assert!(matches!(event.r#type(), EventType::Connect { .. }));
+        drop(event);
+        assert_eq!(
+            client
+                .peers()
+                .filter(|peer| peer.state() != PeerState::Disconnected)
+                .count(),
+            1
+        );
+
+        stop_sender.send(()).unwrap();
+        server_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_poll_no_allocation() {
+        let (mut server, _, mut client, _) = connected_pair::<()>();
+
+        let before = allocations();
+        for _ in 0..1000 {
+            assert!(server.poll().unwrap().is_none());
+            assert!(client.poll().unwrap().is_none());
+        }
+        assert_eq!(allocations(), before);
+    }
+
+    #[test]
+    fn test_connect_channel_count() {
+        let server = create_host(None);
+        let mut client = create_host(None);
+
+        for channel_count in [0, Host::<()>::MAX_CHANNELS + 1] {
+            match client.connect(&server.address(), channel_count, 0) {
+                Err(ConnectError::InvalidChannelCount(count)) => assert_eq!(count, channel_count),
+                result => panic!("unexpected result: {:?}", result.map(|(_, id)| id)),
+            }
+        }
+        assert!(client
+            .peers()
+            .all(|peer| peer.state() == PeerState::Disconnected));
+
+        let (peer, _) = client
+            .connect(&server.address(), Host::<()>::MAX_CHANNELS, 0)
+            .unwrap();
+        assert_eq!(peer.channel_count(), Host::<()>::MAX_CHANNELS);
+    }
+
+    #[test]
+    fn test_connect_validator() {
+        // The server has a single slot, so the accepted client only fits if the rejected one was
+        // removed.
+        let mut server = create_host(Some(&Address::new(Ipv4Addr::LOCALHOST, 0)));
+        let validated = Rc::new(RefCell::new(Vec::new()));
+        let server_validated = validated.clone();
+        server.set_connect_validator(Box::new(move |address, data| {
+            server_validated.borrow_mut().push((*address.ip(), data));
+            data == 42
+        }));
+
+        let mut rejected = create_host(None);
+        rejected.connect(&server.address(), 1, 7).unwrap();
+        wait_for("connecting", || {
+            assert!(server.service(Duration::from_millis(10)).unwrap().is_none());
+            rejected.service(Duration::from_millis(10)).unwrap();
+            (!validated.borrow().is_empty()).then_some(())
+        });
+        assert!(server
+            .peers()
+            .all(|peer| peer.state() == PeerState::Disconnected));
+
+        let mut accepted = create_host(None);
+        accepted.connect(&server.address(), 1, 42).unwrap();
+        let data = service_until(&mut [&mut server, &mut accepted], |host, event| {
+            match (host, event.r#type()) {
+                (0, EventType::Connect { data }) => Some(*data),
+                (0, _) => panic!("unexpected event"),
+                _ => None,
+            }
+        });
+        assert_eq!(data, 42);
+
+        assert_eq!(
+            *validated.borrow(),
+            [(Ipv4Addr::LOCALHOST, 7), (Ipv4Addr::LOCALHOST, 42)]
+        );
+    }
+
+    #[test]
+    fn test_connect_validator_after_reset() {
+        let mut server = localhost_host::<()>(1);
+        let validated = Rc::new(RefCell::new(0));
+        let server_validated = validated.clone();
+        server.set_connect_validator(Box::new(move |_, _| {
+            *server_validated.borrow_mut() += 1;
+            false
+        }));
+
+        // The only slot of the server is first used by an outgoing connection, which is reset.
+        let mut other = localhost_host(1);
+        let (_, other_id) = connect(&mut other, &mut server, 0);
+        server.peer_mut(other_id).unwrap().reset();
+        assert!(server.peer(other_id).is_none());
+
+        // The incoming connection reusing the slot is still validated.
+        let mut client = create_host(None);
+        client.connect(&server.address(), 1, 0).unwrap();
+        wait_for("connecting", || {
+            assert!(server.service(Duration::from_millis(10)).unwrap().is_none());
+            client.service(Duration::from_millis(10)).unwrap();
+            (*validated.borrow() > 0).then_some(())
+        });
+        assert!(server
+            .peers()
+            .all(|peer| peer.state() == PeerState::Disconnected));
+    }
+
+    #[test]
+    fn test_default_peer_timeout() {
+        let timeout = |limit, secs| PeerTimeout {
+            limit,
+            minimum: Duration::from_secs(secs),
+            maximum: Duration::from_secs(2 * secs),
+        };
+        let peer_timeout = |peer: *const enet_sys::ENetPeer| {
+            let peer = unsafe { &*peer };
+            (peer.timeoutLimit, peer.timeoutMinimum, peer.timeoutMaximum)
+        };
+
+        let mut server = create_host(Some(&Address::new(Ipv4Addr::LOCALHOST, 0)));
+        let mut client = create_host(None);
+        server.set_default_peer_timeout(Some(timeout(40, 8)));
+        client.set_default_peer_timeout(Some(timeout(50, 9)));
+        assert_eq!(client.default_peer_timeout(), Some(timeout(50, 9)));
+
+        let (peer, server_id) = client.connect(&server.address(), 1, 0).unwrap();
+        assert_eq!(peer_timeout(peer.as_raw()), (50, 9000, 18000));
+
+        let mut connected = [false; 2];
+        service_until(&mut [&mut server, &mut client], |host, event| {
+            if let EventType::Connect { .. } = event.r#type() {
+                if host == 0 {
+                    assert_eq!(peer_timeout(event.peer().as_raw()), (40, 8000, 16000));
+                }
+                connected[host] = true;
+            }
+            (connected == [true; 2]).then_some(())
+        });
+        let peer = client.peer(server_id).unwrap();
+        assert_eq!(peer_timeout(peer.as_raw()), (50, 9000, 18000));
+
+        // The timeout of a `PeerConfig` takes precedence.
+        let config = PeerConfig {
+            timeout: Some(timeout(60, 10)),
+            ..PeerConfig::default()
+        };
+        let mut other = create_host(None);
+        other.set_default_peer_timeout(Some(timeout(50, 9)));
+        let (peer, _) = other
+            .connect_with(&server.address(), 1, 0, &config)
+            .unwrap();
+        assert_eq!(peer_timeout(peer.as_raw()), (60, 10000, 20000));
+    }
+
+    #[test]
+    fn test_default_peer_timeout_after_reset() {
+        let mut server = localhost_host::<()>(1);
+        server.set_default_peer_timeout(Some(PeerTimeout {
+            limit: 40,
+            minimum: Duration::from_secs(8),
+            maximum: Duration::from_secs(16),
+        }));
+
+        // The only slot of the server is first used by an outgoing connection, which ENet resets
+        // without the peer being cleaned up.
+        let other = localhost_host::<()>(1);
+        let (peer, _) = server.connect(&other.address(), 1, 0).unwrap();
+        unsafe { enet_sys::enet_peer_reset(peer.as_raw_mut()) };
+
+        // The incoming connection reusing the slot still gets the default timeout.
+        let mut client = create_host(None);
+        let (client_id, _) = connect(&mut server, &mut client, 0);
+        let peer = unsafe { &*server.peer(client_id).unwrap().as_raw() };
+        assert_eq!(
+            (peer.timeoutLimit, peer.timeoutMinimum, peer.timeoutMaximum),
+            (40, 8000, 16000)
+        );
+    }
+
+    #[test]
+    fn test_close_gracefully() {
+        let (port_sender, port_receiver) = mpsc::channel();
+        let server_thread = thread::spawn(move || {
+            let mut server = create_host(Some(&Address::new(Ipv4Addr::LOCALHOST, 0)));
+            port_sender.send(server.address().port()).unwrap();
+
+            let mut received = Vec::new();
+            let data = service_until(&mut [&mut server], |_, event| match event.take_type() {
+                EventType::Receive { packet, .. } => {
+                    received.push(packet.data().to_vec());
+                    None
+                }
+                EventType::Disconnect { data } => Some(data),
+                EventType::Connect { .. } => None,
+            });
+            (received, data)
+        });
+
+        let mut client = create_host(None);
+        let server = Address::new(Ipv4Addr::LOCALHOST, port_receiver.recv().unwrap());
+        let (_, server_id) = client.connect(&server, 1, 0).unwrap();
+        wait_for("connecting", || {
+            client.service(Duration::from_millis(10)).unwrap();
+            (client.peer(server_id).unwrap().state() == PeerState::Connected).then_some(())
+        });
+
+        let peer = client.peer_mut(server_id).unwrap();
+        peer.send_owned(vec![1; 5000], PacketMode::ReliableSequenced, 0)
+            .unwrap();
+        assert_eq!(
+            client
+                .close_gracefully(server_id, 3, Duration::from_secs(5))
+                .unwrap(),
+            CloseOutcome::Closed
+        );
+
+        // The `Disconnect` event is still delivered.
+        match client.check_events().unwrap() {
+            Some(event) => assert!(matches!(event.r#type(), EventType::Disconnect { .. })),
+            None => panic!("no disconnect event"),
+        }
+        assert!(client.peer(server_id).is_none());
+        assert_eq!(
+            client
+                .close_gracefully(server_id, 3, Duration::from_secs(5))
+                .unwrap(),
+            CloseOutcome::Closed
+        );
+
+        let (received, data) = server_thread.join().unwrap();
+        assert_eq!(received, [vec![1; 5000]]);
+        assert_eq!(data, 3);
+    }
+
+    #[test]
+    fn test_close_gracefully_timeout() {
+        // The server is never serviced, so it doesn't acknowledge the disconnect.
+        let (_server, _, mut client, server_id) = connected_pair::<()>();
+
+        assert_eq!(
+            client
+                .close_gracefully(server_id, 0, Duration::from_millis(200))
+                .unwrap(),
+            CloseOutcome::TimedOut
+        );
+        assert!(client.peer(server_id).is_none());
+        assert!(client
+            .peers()
+            .all(|peer| peer.state() == PeerState::Disconnected));
+        assert!(client.check_events().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_measure_rtt() {
+        let (port_sender, port_receiver) = mpsc::channel();
+        let server_thread = thread::spawn(move || {
+            let mut server = create_host(Some(&Address::new(Ipv4Addr::LOCALHOST, 0)));
+            port_sender.send(server.address().port()).unwrap();
+
+            service_until(&mut [&mut server], |_, event| match event.r#type() {
+                EventType::Disconnect { .. } => Some(()),
+                _ => None,
+            });
+        });
+
+        let mut client = create_host(None);
+        let server = Address::new(Ipv4Addr::LOCALHOST, port_receiver.recv().unwrap());
+        let (_, server_id) = client.connect(&server, 1, 0).unwrap();
+        wait_for("connecting", || {
+            client.service(Duration::from_millis(10)).unwrap();
+            (client.peer(server_id).unwrap().state() == PeerState::Connected).then_some(())
+        });
+
+        let rtt = client
+            .measure_rtt(server_id, Duration::from_secs(5))
+            .unwrap()
+            .unwrap();
+        assert!(rtt < Duration::from_secs(1));
+
+        let ping = client.peer_mut(server_id).unwrap().ping().unwrap();
+        assert!(!client.peer(server_id).unwrap().ping_acknowledged(&ping));
+        wait_for("ping", || {
+            client.service(Duration::from_millis(1)).unwrap();
+            client
+                .peer(server_id)
+                .unwrap()
+                .ping_acknowledged(&ping)
+                .then_some(())
+        });
+
+        client.peer_mut(server_id).unwrap().disconnect(0).unwrap();
+        client.flush();
+        server_thread.join().unwrap();
+
+        // Pings need a connected peer.
+        assert!(client.peer_mut(server_id).unwrap().ping().is_none());
+        assert_eq!(
+            client
+                .measure_rtt(server_id, Duration::from_secs(1))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_compressor_incompressible() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+        server.set_compressor(Compressor::RangeCoder).unwrap();
+        client.set_compressor(Compressor::RangeCoder).unwrap();
+
+        let mut random = xorshift();
+        let random: Vec<u8> = (0..4000).map(|_| random() as u8).collect();
+
+        let mut send = |data: Vec<u8>| {
+            let before = unsafe { (*client.as_raw()).totalSentData };
+            let peer = client.peer_mut(server_id).unwrap();
+            peer.send_owned(data, PacketMode::ReliableSequenced, 0)
+                .unwrap();
+            client.flush();
+            unsafe { (*client.as_raw()).totalSentData - before }
+        };
+        // Zeros compress well, random data is sent uncompressed instead of growing. Each
+        // datagram carrying a fragment adds a few bytes of headers.
+        assert!(send(vec![0; 4000]) < 1000);
+        let sent = send(random.clone());
+        assert!((4000..4200).contains(&sent), "sent {} bytes", sent);
+
+        let received: Vec<_> = (0..2)
+            .map(|_| {
+                service_until(&mut [&mut client, &mut server], |_, event| {
+                    event.take_packet().map(|packet| packet.data().to_vec())
+                })
+            })
+            .collect();
+        assert_eq!(received, [vec![0; 4000], random]);
+    }
+
+    #[test]
+    fn test_event_order_per_peer() {
+        const PACKETS: u8 = 20;
+
+        let mut server = ENET
+            .create_host::<()>(
+                Some(&Address::new(Ipv4Addr::LOCALHOST, 0)),
+                2,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+            .unwrap();
+        let mut clients: Vec<_> = (0..2)
+            .map(|_| {
+                let mut client = create_host(None);
+                let (_, server_id) = client.connect(&server.address(), 2, 0).unwrap();
+                (client, server_id, false)
+            })
+            .collect();
+
+        let mut events: Vec<(PeerID, Option<u8>)> = Vec::new();
+        let mut disconnects = 0;
+        wait_for("events", || {
+            server
+                .for_each_event(Duration::from_millis(5), |event| {
+                    let peer_id = event.peer_id();
+                    match event.take_type() {
+                        EventType::Connect { .. } => events.push((peer_id, None)),
+                        EventType::Receive { packet, .. } => {
+                            events.push((peer_id, Some(packet.data()[0])))
+                        }
+                        EventType::Disconnect { .. } => {
+                            events.push((peer_id, None));
+                            disconnects += 1;
+                        }
+                    }
+                })
+                .unwrap();
+
+            for (client, server_id, sent) in &mut clients {
+                client.service(Duration::ZERO).unwrap();
+
+                let peer = match client.peer_mut(*server_id) {
+                    Some(peer) if peer.state() == PeerState::Connected && !*sent => peer,
+                    _ => continue,
+                };
+                for i in 0..PACKETS {
+                    peer.send_owned(vec![i], PacketMode::ReliableSequenced, 0)
+                        .unwrap();
+                }
+                peer.disconnect_later(1).unwrap();
+                *sent = true;
+            }
+            (disconnects == clients.len()).then_some(())
+        });
+
+        let mut peer_ids: Vec<_> = events.iter().map(|(peer_id, _)| *peer_id).collect();
+        peer_ids.sort_by_key(|peer_id| peer_id.index);
+        peer_ids.dedup();
+        assert_eq!(peer_ids.len(), 2);
+
+        // Every connection sees its connect, all packets in order, and then its disconnect.
+        let mut expected = vec![None];
+        expected.extend((0..PACKETS).map(Some));
+        expected.push(None);
+        for peer_id in peer_ids {
+            let peer_events: Vec<_> = events
+                .iter()
+                .filter(|(id, _)| *id == peer_id)
+                .map(|(_, data)| *data)
+                .collect();
+            assert_eq!(peer_events, expected);
+        }
+    }
+
+    #[test]
+    fn test_on_channel() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+
+        let handled = Rc::new(RefCell::new(Vec::new()));
+        let server_handled = handled.clone();
+        server.on_channel(
+            1,
+            Box::new(move |peer, packet| {
+                server_handled.borrow_mut().push(packet.data().to_vec());
+                // Handlers can reply through the peer.
+                peer.send_packet(packet, 1).unwrap();
+            }),
+        );
+
+        // Services both hosts until the server returned `event_count` events and the client
+        // received `reply_count` replies.
+        let exchange = |client: &mut Host<()>,
+                        server: &mut Host<()>,
+                        event_count: usize,
+                        reply_count: usize| {
+            let peer = client.peer_mut(server_id).unwrap();
+            peer.send_owned(vec![0], PacketMode::ReliableSequenced, 0)
+                .unwrap();
+            peer.send_owned(vec![1], PacketMode::ReliableSequenced, 1)
+                .unwrap();
+            client.flush();
+
+            let mut server_events = Vec::new();
+            let mut replies = Vec::new();
+            wait_for("exchanging", || {
+                if let Some(event) = client.service(Duration::from_millis(10)).unwrap() {
+                    replies.extend(event.take_packet().map(|packet| packet.data().to_vec()));
+                }
+                if let Some(event) = server.service(Duration::from_millis(10)).unwrap() {
+                    if let EventType::Receive { channel_id, .. } = event.r#type() {
+                        server_events.push(*channel_id);
+                    }
+                }
+                (server_events.len() >= event_count && replies.len() >= reply_count).then_some(())
+            });
+
+            (server_events, replies)
+        };
+
+        assert_eq!(
+            exchange(&mut client, &mut server, 1, 1),
+            (vec![0], vec![vec![1]])
+        );
+        assert_eq!(*handled.borrow(), [vec![1]]);
+        // Only the packet returned as an event is counted.
+        assert_eq!(server.event_counts().receives, 1);
+
+        server.clear_channel_handler(1);
+        assert_eq!(
+            exchange(&mut client, &mut server, 2, 0),
+            (vec![0, 1], vec![])
+        );
+        assert_eq!(handled.borrow().len(), 1);
     }
 }
@@ -0,0 +1,242 @@
+use std::{
+    io,
+    os::unix::io::AsRawFd,
+    time::{Duration, Instant},
+};
+
+use crate::{Error, Host, OwnedEvent};
+
+/// An error that can occur when servicing a `HostGroup`.
+#[derive(thiserror::Error, Debug)]
+pub enum HostGroupError {
+    /// Servicing one of the hosts failed.
+    #[error(transparent)]
+    Enet(#[from] Error),
+    /// Waiting for the sockets of the hosts with `poll(2)` failed.
+    #[error("failed to poll the hosts: {}", .0)]
+    Poll(#[from] io::Error),
+}
+
+/// The index of a `Host` inside a `HostGroup`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HostIndex(pub usize);
+
+/// A group of `Host`s that are serviced together.
+///
+/// Servicing a `HostGroup` waits for any of its hosts to become readable with a single
+/// `poll(2)` call, instead of blocking on every `Host` one after another.
+///
+/// Only available on unix platforms.
+pub struct HostGroup<T> {
+    hosts: Vec<Host<T>>,
+}
+
+impl<T> HostGroup<T> {
+    /// Creates an empty `HostGroup`.
+    pub fn new() -> HostGroup<T> {
+        HostGroup { hosts: Vec::new() }
+    }
+
+    /// Adds a `Host` to this group, returning its index.
+    pub fn push(&mut self, host: Host<T>) -> HostIndex {
+        self.hosts.push(host);
+        HostIndex(self.hosts.len() - 1)
+    }
+
+    /// Returns the number of hosts in this group.
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    /// Returns whether this group contains no hosts.
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    /// Returns a reference to the `Host` at the given index, None if the index is invalid.
+    pub fn host(&self, idx: HostIndex) -> Option<&Host<T>> {
+        self.hosts.get(idx.0)
+    }
+
+    /// Returns a mutable reference to the `Host` at the given index, None if the index is invalid.
+    pub fn host_mut(&mut self, idx: HostIndex) -> Option<&mut Host<T>> {
+        self.hosts.get_mut(idx.0)
+    }
+
+    /// Maintains all hosts of this group and returns the events that occurred.
+    ///
+    /// Every host is serviced once without blocking, so that outgoing packets are sent and ENet's
+    /// timers keep running. If none of the hosts produced an event, this waits up to `timeout` for
+    /// any of their sockets to become readable, and then services only the ready hosts.
+    ///
+    /// All pending events of a serviced host are returned, converted into `OwnedEvent`s (see
+    /// `Event::into_owned`). If the wait is interrupted by a signal, it continues for the rest of
+    /// `timeout`.
+    pub fn service(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Vec<(HostIndex, OwnedEvent)>, HostGroupError> {
+        let mut events = Vec::new();
+
+        for idx in 0..self.hosts.len() {
+            self.drain(HostIndex(idx), &mut events)?;
+        }
+
+        if !events.is_empty() || self.hosts.is_empty() {
+            return Ok(events);
+        }
+
+        let mut fds: Vec<_> = self
+            .hosts
+            .iter()
+            .map(|host| libc::pollfd {
+                fd: host.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let res = unsafe {
+                libc::poll(
+                    fds.as_mut_ptr(),
+                    fds.len() as libc::nfds_t,
+                    remaining.as_millis().try_into().unwrap_or(libc::c_int::MAX),
+                )
+            };
+
+            if res >= 0 {
+                break;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err.into());
+            }
+        }
+
+        for (idx, fd) in fds.iter().enumerate() {
+            if fd.revents != 0 {
+                self.drain(HostIndex(idx), &mut events)?;
+            }
+        }
+
+        Ok(events)
+    }
+
+    fn drain(
+        &mut self,
+        idx: HostIndex,
+        events: &mut Vec<(HostIndex, OwnedEvent)>,
+    ) -> Result<(), Error> {
+        let host = &mut self.hosts[idx.0];
+
+        while let Some(event) = host.service(Duration::ZERO)? {
+            events.push((idx, event.into_owned()));
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Default for HostGroup<T> {
+    fn default() -> HostGroup<T> {
+        HostGroup::new()
+    }
+}
+
+impl<T> IntoIterator for HostGroup<T> {
+    type Item = Host<T>;
+    type IntoIter = std::vec::IntoIter<Host<T>>;
+
+    /// Returns the hosts of this group.
+    fn into_iter(self) -> Self::IntoIter {
+        self.hosts.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::Ipv4Addr,
+        time::{Duration, Instant},
+    };
+
+    use super::{HostGroup, HostIndex};
+    use crate::{
+        test_util::wait_for, tests::ENET, Address, BandwidthLimit, ChannelLimit, EventType, Host,
+    };
+
+    fn create_host(address: Option<&Address>) -> Host<()> {
+        ENET.create_host(
+            address,
+            1,
+            ChannelLimit::Maximum,
+            BandwidthLimit::Unlimited,
+            BandwidthLimit::Unlimited,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_service_group() {
+        let localhost = Address::new(Ipv4Addr::LOCALHOST, 0);
+
+        let mut group = HostGroup::new();
+        group.push(create_host(Some(&localhost)));
+        let second = group.push(create_host(Some(&localhost)));
+        let client_idx = group.push(create_host(None));
+
+        let server_address = group.host(second).unwrap().address();
+        group
+            .host_mut(client_idx)
+            .unwrap()
+            .connect(&server_address, 1, 0)
+            .unwrap();
+
+        let mut connected = Vec::new();
+        wait_for("connecting", || {
+            for (idx, event) in group.service(Duration::from_millis(100)).unwrap() {
+                if let EventType::Connect { .. } = event.r#type {
+                    connected.push(idx);
+                }
+            }
+            (connected.len() == 2).then_some(())
+        });
+
+        connected.sort_by_key(|idx| idx.0);
+        assert_eq!(connected, vec![HostIndex(1), HostIndex(2)]);
+    }
+
+    #[test]
+    fn test_service_interrupted() {
+        extern "C" fn ignore(_: libc::c_int) {}
+
+        let mut group = HostGroup::new();
+        group.push(create_host(Some(&Address::new(Ipv4Addr::LOCALHOST, 0))));
+
+        // Interrupt the `poll(2)` call of this thread with a signal that is otherwise ignored.
+        unsafe {
+            libc::signal(
+                libc::SIGUSR1,
+                ignore as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+        let thread = unsafe { libc::pthread_self() };
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            unsafe {
+                libc::pthread_kill(thread, libc::SIGUSR1);
+            }
+        });
+
+        let start = Instant::now();
+        assert!(group
+            .service(Duration::from_millis(200))
+            .unwrap()
+            .is_empty());
+        assert!(start.elapsed() >= Duration::from_millis(190));
+        interrupter.join().unwrap();
+    }
+}
@@ -20,7 +20,10 @@
 //! // `Enet::new()` initializes ENet when it is first called.
 //! let enet = Enet::new().unwrap();
 //!
-//! // Deinitialization is handled automatically (using Arc).
+//! // Further calls share the initialization, e.g. in another library of the same process.
+//! let other = Enet::new().unwrap();
+//!
+//! // Deinitialization is handled automatically, once all instances are dropped.
 //! ```
 //!
 //! Also check out the examples in the code, as well as the examples from the official ENet website and from the enet-sys crate. There are also an example server and client in the `examples` directory on [github](https://github.com/futile/enet-rs).
@@ -33,6 +36,27 @@
 //! should be safe to do so.
 //!
 //! If you used no unsafe code and the library blows up in your face, that is considered a bug. Please report any bug you encounter via [github](https://github.com/futile/enet-rs).
+//!
+//! # Dependencies on `std`
+//! This crate requires `std`, mostly because `enet-sys` and ENet itself do: ENet uses the C
+//! standard library for memory allocation, time and sockets, and `thiserror` implements
+//! `std::error::Error` for the error types. Besides that, the following is used from `std`:
+//!
+//! - `std::time::Instant`, for the deadlines of `Host::connect_any`, `Host::shutdown`,
+//!   `Host::close_gracefully`, `Host::measure_rtt` and `HostGroup::service`, for the time of the
+//!   last event in `Host::service_adaptive`, and for the send time of the pings of `Peer::ping`.
+//! - `std::sync::Mutex`, for counting the `Enet` instances that keep ENet initialized.
+//! - `std::io::Error`, for the errors of `HostGroup::service` and
+//!   `Enet::create_host_from_socket`.
+//! - `std::os::unix` and `poll(2)` (through `libc`), for `HostGroup` and `AsRawFd`, on unix only.
+//! - `std::net::UdpSocket`, for `Enet::create_host_from_socket`, on unix only.
+//! - `std::sync::OnceLock`, for the shared `Enet` of the `test_util` module, with the `test-util`
+//!   feature only.
+//!
+//! Everything else, e.g. the construction of packets and the handling of their flags, only
+//! needs what is also available in `core` (`Duration`, `Ipv4Addr`, `CStr`, pointers, atomics)
+//! and `alloc` (`Vec`, `Box`, `Arc`). There is no `no_std` feature, as ENet can't be used
+//! without the C standard library anyway.
 
 #![warn(missing_docs)]
 
@@ -41,45 +65,78 @@
 extern crate lazy_static;
 
 use std::{
+    fmt::{self, Display, Formatter},
     os::raw::c_int,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
+    sync::{Arc, Mutex},
 };
 
-use enet_sys::{enet_deinitialize, enet_host_create, enet_initialize, enet_linked_version};
+use enet_sys::{
+    enet_deinitialize, enet_host_create, enet_initialize, enet_linked_version, enet_time_get,
+    ENET_PROTOCOL_MAXIMUM_PEER_ID, ENET_VERSION_MAJOR, ENET_VERSION_MINOR, ENET_VERSION_PATCH,
+};
 
 mod address;
+mod coalesce;
 mod event;
 mod host;
+#[cfg(unix)]
+mod host_group;
 mod packet;
 mod peer;
+mod service_loop;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 
 pub use enet_sys::ENetVersion as EnetVersion;
 
 pub use crate::{
-    address::Address,
-    event::{Event, EventType},
-    host::{BandwidthLimit, ChannelLimit, Host},
-    packet::{Packet, PacketMode},
-    peer::{Peer, PeerID, PeerState},
+    address::{Address, AddressError, Subnet},
+    coalesce::{write_frame, CoalescedMessages, FrameReader, FramingError},
+    event::{Event, EventType, OwnedEvent},
+    host::{
+        BandwidthLimit, CaptureHook, ChannelHandler, ChannelLimit, CloseOutcome, Compressor,
+        ConnectValidator, Direction, EventCounts, Host, MemoryPressureHandler, PacketFilter,
+        SendAccountant,
+    },
+    packet::{Packet, PacketFlags, PacketFlagsError, PacketMode, PacketRef},
+    peer::{Peer, PeerConfig, PeerID, PeerState, PeerThrottle, PeerTimeout, Ping},
+    service_loop::ServiceLoop,
 };
 
-const ENET_UNINITIALIZED: usize = 1;
-const ENET_INITIALIZED: usize = 2;
-const ENET_DEINITIALIZED: usize = 3;
+#[cfg(unix)]
+pub use crate::host_group::{HostGroup, HostGroupError, HostIndex};
 
-static ENET_STATUS: AtomicUsize = AtomicUsize::new(ENET_UNINITIALIZED);
+/// The number of `EnetKeepAlive`s that exist, ENet is initialized while it isn't 0.
+///
+/// The lock is held while (de)initializing ENet, so a new initialization can't overlap with the
+/// deinitialization by the last `EnetKeepAlive`.
+static ENET_REFS: Mutex<usize> = Mutex::new(0);
 
-#[derive(Debug, Clone)]
+/// Keeps ENet initialized until it is dropped. Created by every successful `Enet::new`.
+#[derive(Debug)]
 struct EnetKeepAlive;
 
+impl EnetKeepAlive {
+    fn acquire() -> Result<EnetKeepAlive, InitializationError> {
+        let mut refs = ENET_REFS.lock().unwrap_or_else(|err| err.into_inner());
+        if *refs == 0 {
+            let r = unsafe { enet_initialize() };
+            if r != 0 {
+                return Err(InitializationError::Error(r));
+            }
+        }
+
+        *refs += 1;
+        Ok(EnetKeepAlive)
+    }
+}
+
 /// Main API entry point. Provides methods such as host and peer creation.
 ///
-/// Creating an instance of this struct for the first time (using `new()`) will
-/// initialize ENet. Further attempts to create instances will result in errors,
-/// so it can only be constructed once (but it can be cloned).
+/// Creating the first instance of this struct (using `new()`) initializes ENet, and dropping the
+/// last one (including its clones, and the `Host`s created with it) deinitializes it again.
+/// Instances can be created any number of times, e.g. by independent libraries of the same
+/// process, which then share the initialization.
 ///
 /// This struct can be used to performs most top-level ENet functionality, such
 /// as host creation and connection establishment.
@@ -95,15 +152,77 @@ pub struct Enet {
 #[error("enet failure, returned '{}'", .0)]
 pub struct Error(pub c_int);
 
+/// An error that can occur when connecting with `Host::connect_any`.
+#[derive(thiserror::Error, Debug)]
+pub enum ConnectError {
+    /// The list of addresses was empty.
+    #[error("no addresses to connect to")]
+    NoAddresses,
+    /// None of the addresses could be connected to within the timeout.
+    #[error("none of the addresses could be connected to")]
+    Failed,
+    /// The channel count is outside of `Host::MIN_CHANNELS..=Host::MAX_CHANNELS`.
+    #[error("invalid channel count {}, has to be between 1 and 255", .0)]
+    InvalidChannelCount(usize),
+    /// ENet failed while connecting or servicing the host.
+    #[error(transparent)]
+    Enet(#[from] Error),
+}
+
+/// An error that can occur when disconnecting a `Peer`.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectError {
+    /// The peer is already disconnecting or disconnected, contains the state of the peer.
+    #[error("the peer is already disconnecting or disconnected (state: {:?})", .0)]
+    AlreadyDisconnecting(PeerState),
+}
+
+/// An error that can occur when sending a packet to a `Peer`.
+#[derive(thiserror::Error, Debug)]
+pub enum PeerSendError {
+    /// The packet is larger than `Peer::max_packet_size`.
+    #[error("packet of {len} bytes exceeds the maximum packet size of {max} bytes")]
+    TooLarge {
+        /// The length of the packet.
+        len: usize,
+        /// The maximum packet size.
+        max: usize,
+    },
+    /// ENet failed to queue the packet, e.g. because the channel doesn't exist.
+    #[error(transparent)]
+    Enet(#[from] Error),
+}
+
+/// An error that can occur when creating a `Host` with `Enet::create_host`.
+#[derive(thiserror::Error, Debug)]
+pub enum HostCreateError {
+    /// An argument has a value that ENet doesn't support, `field` names the argument.
+    #[error("invalid host configuration: `{field}` {reason}")]
+    InvalidConfig {
+        /// The name of the invalid argument.
+        field: &'static str,
+        /// Why the value is invalid.
+        reason: &'static str,
+    },
+    /// ENet failed to create the host, e.g. because the address is already in use.
+    #[error(transparent)]
+    Enet(#[from] Error),
+    /// The socket passed to `Enet::create_host_from_socket` couldn't be configured.
+    #[error("failed to configure the socket: {}", .0)]
+    Socket(#[from] std::io::Error),
+}
+
 /// An error that can occur when initializing ENet.
 #[derive(thiserror::Error, Debug)]
 pub enum InitializationError {
-    /// ENet was already initialized. `Enet::new()` can only (successfully) be
-    /// called once, so reuse that object.
+    /// ENet was already initialized.
+    ///
+    /// No longer returned, as `Enet::new()` shares an existing initialization.
     #[error("ENet has already been initialized before")]
     AlreadyInitialized,
-    /// ENet was already deinitialized. Probably continue using your previous
-    /// `Enet`-instance.
+    /// ENet was already deinitialized.
+    ///
+    /// No longer returned, as `Enet::new()` initializes ENet again after it was deinitialized.
     #[error("ENet has already been deinitialized before")]
     AlreadyDeinitialized,
     /// Internal ENet failure (`enet_initialize` failed), containing the return
@@ -113,32 +232,29 @@ pub enum InitializationError {
 }
 
 impl Enet {
+    /// Returns the current ENet time in milliseconds, the time base of `Event::timestamp`.
+    ///
+    /// ENet measures time from an arbitrary point, so this is only meaningful relative to other
+    /// ENet times.
+    pub fn time(&self) -> u32 {
+        unsafe { enet_time_get() }
+    }
+
+    /// Returns the version of the linked ENet library.
+    ///
+    /// This can differ from `Version::HEADERS` if ENet is linked dynamically.
+    pub fn linked_version() -> Version {
+        Version::from_raw(linked_version())
+    }
+
     /// Initializes ENet and returns a handle to the top-level functionality, in
     /// the form of an `Enet`-instance.
+    ///
+    /// ENet is only initialized if no other instance exists, otherwise the instances share the
+    /// initialization. ENet is deinitialized once all instances were dropped (see `Enet`).
     pub fn new() -> Result<Enet, InitializationError> {
-        match ENET_STATUS.compare_exchange(
-            ENET_UNINITIALIZED,
-            ENET_INITIALIZED,
-            Ordering::SeqCst,
-            Ordering::SeqCst,
-        ) {
-            Ok(_) => (),
-            Err(ENET_INITIALIZED) => return Err(InitializationError::AlreadyInitialized),
-            Err(ENET_DEINITIALIZED) => return Err(InitializationError::AlreadyDeinitialized),
-            Err(u) => panic!(
-                "enet-rs internal error; unexpected value in ENET_STATUS (new): {}",
-                u
-            ),
-        };
-
-        let r = unsafe { enet_initialize() };
-
-        if r != 0 {
-            return Err(InitializationError::Error(r));
-        }
-
         Ok(Enet {
-            keep_alive: Arc::new(EnetKeepAlive),
+            keep_alive: Arc::new(EnetKeepAlive::acquire()?),
         })
     }
 
@@ -150,6 +266,36 @@ impl Enet {
     /// (ENet-specified) default value if `None`.
     ///
     /// The type `T` specifies the data associated with corresponding `Peer`s.
+    ///
+    /// Arguments that ENet doesn't support, like a `max_peer_count` of 0, are rejected with
+    /// `HostCreateError::InvalidConfig` before ENet is called.
+    ///
+    /// # Fixed source port
+    /// ENet binds the socket of a `Host` when it is created, and sends the packets of all its
+    /// connections from that socket, so the source port can't be chosen per connection. To
+    /// connect from a specific local port (e.g. for NAT traversal via port prediction), create
+    /// the client bound to that port and connect from it. Client hosts can be bound like any
+    /// server:
+    ///
+    /// ```no_run
+    /// # use std::net::Ipv4Addr;
+    /// # use enet::*;
+    /// # let enet = Enet::new().unwrap();
+    /// let mut client = enet
+    ///     .create_host::<()>(
+    ///         Some(&Address::new(Ipv4Addr::UNSPECIFIED, 40000)),
+    ///         1,
+    ///         ChannelLimit::Maximum,
+    ///         BandwidthLimit::Unlimited,
+    ///         BandwidthLimit::Unlimited,
+    ///     )
+    ///     .unwrap();
+    ///
+    /// // Every packet of this connection is sent from port 40000.
+    /// client
+    ///     .connect(&"203.0.113.7:9001".parse().unwrap(), 1, 0)
+    ///     .unwrap();
+    /// ```
     pub fn create_host<T>(
         &self,
         address: Option<&Address>,
@@ -157,7 +303,14 @@ impl Enet {
         max_channel_count: ChannelLimit,
         incoming_bandwidth: BandwidthLimit,
         outgoing_bandwidth: BandwidthLimit,
-    ) -> Result<Host<T>, Error> {
+    ) -> Result<Host<T>, HostCreateError> {
+        validate_host_config(
+            max_peer_count,
+            max_channel_count,
+            incoming_bandwidth,
+            outgoing_bandwidth,
+        )?;
+
         let addr = address.map(Address::to_enet_address);
         let inner = unsafe {
             enet_host_create(
@@ -172,36 +325,420 @@ impl Enet {
         };
 
         if inner.is_null() {
-            return Err(Error(0));
+            return Err(Error(0).into());
         }
 
         Ok(Host::new(self.keep_alive.clone(), inner))
     }
+
+    /// Creates a `Host` that uses an existing, bound UDP socket instead of creating its own.
+    ///
+    /// This allows configuring the socket before ENet uses it, e.g. to share a port between the
+    /// hosts of several worker threads with `SO_REUSEPORT`, which has to be set before binding.
+    /// The other arguments are the same as for `Enet::create_host`.
+    ///
+    /// The `Host` takes ownership of the socket, and closes it when it is dropped. The socket is
+    /// switched to non-blocking mode, as ENet requires. Unlike for its own sockets, ENet doesn't
+    /// enable `SO_BROADCAST` or enlarge the socket buffers (256KiB each by default), so this is up
+    /// to the caller.
+    ///
+    /// The socket has to be bound to an IPv4 address, as ENet doesn't support IPv6. Only
+    /// available on unix platforms, where ENet sockets are file descriptors.
+    ///
+    /// ```no_run
+    /// # use std::net::UdpSocket;
+    /// # use enet::*;
+    /// # let enet = Enet::new().unwrap();
+    /// // Configure the socket as required before binding it, e.g. with the `socket2` crate.
+    /// let socket = UdpSocket::bind("0.0.0.0:9001").unwrap();
+    /// let host = enet
+    ///     .create_host_from_socket::<()>(
+    ///         socket,
+    ///         32,
+    ///         ChannelLimit::Maximum,
+    ///         BandwidthLimit::Unlimited,
+    ///         BandwidthLimit::Unlimited,
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(host.address().port(), 9001);
+    /// ```
+    #[cfg(unix)]
+    pub fn create_host_from_socket<T>(
+        &self,
+        socket: std::net::UdpSocket,
+        max_peer_count: enet_sys::size_t,
+        max_channel_count: ChannelLimit,
+        incoming_bandwidth: BandwidthLimit,
+        outgoing_bandwidth: BandwidthLimit,
+    ) -> Result<Host<T>, HostCreateError> {
+        use std::{net::SocketAddr, os::unix::io::IntoRawFd};
+
+        use enet_sys::enet_socket_destroy;
+
+        let address = match socket.local_addr()? {
+            SocketAddr::V4(addr) => Address::from(addr),
+            SocketAddr::V6(_) => {
+                return Err(HostCreateError::InvalidConfig {
+                    field: "socket",
+                    reason: "has to be bound to an IPv4 address",
+                })
+            }
+        };
+        socket.set_nonblocking(true)?;
+
+        // ENet doesn't bind the socket of a host without an address, so it can simply be
+        // replaced.
+        let host = self.create_host(
+            None,
+            max_peer_count,
+            max_channel_count,
+            incoming_bandwidth,
+            outgoing_bandwidth,
+        )?;
+        unsafe {
+            let inner = host.as_raw();
+            enet_socket_destroy((*inner).socket);
+            (*inner).socket = socket.into_raw_fd();
+            (*inner).address = address.to_enet_address();
+        }
+
+        Ok(host)
+    }
+}
+
+/// Checks the arguments of `Enet::create_host` for values ENet would reject, or silently
+/// interpret differently.
+fn validate_host_config(
+    max_peer_count: usize,
+    max_channel_count: ChannelLimit,
+    incoming_bandwidth: BandwidthLimit,
+    outgoing_bandwidth: BandwidthLimit,
+) -> Result<(), HostCreateError> {
+    const MAX_PEER_COUNT: usize = ENET_PROTOCOL_MAXIMUM_PEER_ID as usize;
+    let invalid = |field, reason| Err(HostCreateError::InvalidConfig { field, reason });
+
+    if !(1..=MAX_PEER_COUNT).contains(&max_peer_count) {
+        return invalid("max_peer_count", "has to be between 1 and 4095");
+    }
+    if let ChannelLimit::Limited(limit) = max_channel_count {
+        if !(Host::<()>::MIN_CHANNELS..=Host::<()>::MAX_CHANNELS).contains(&limit) {
+            return invalid("max_channel_count", "has to be between 1 and 255");
+        }
+    }
+    // ENet uses a limit of 0 for unlimited bandwidth.
+    for (field, bandwidth) in [
+        ("incoming_bandwidth", incoming_bandwidth),
+        ("outgoing_bandwidth", outgoing_bandwidth),
+    ] {
+        if bandwidth == BandwidthLimit::Limited(0) {
+            return invalid(field, "use `BandwidthLimit::Unlimited` instead of 0");
+        }
+    }
+
+    Ok(())
 }
 
 /// Returns the version of the linked ENet library.
+///
+/// See `Enet::linked_version` for a version with separate components.
 pub fn linked_version() -> EnetVersion {
     unsafe { enet_linked_version() }
 }
 
+/// A version of the ENet library, split into its components.
+///
+/// Versions are ordered by their components, so a minimum version can be checked with a simple
+/// comparison:
+///
+/// ```
+/// # use enet::*;
+/// assert!(Enet::linked_version() >= Version::new(1, 3, 0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    /// The major version.
+    pub major: u8,
+    /// The minor version.
+    pub minor: u8,
+    /// The patch version.
+    pub patch: u8,
+}
+
+impl Version {
+    /// The version of the ENet headers this crate was built against (`ENET_VERSION`).
+    pub const HEADERS: Version = Version::new(
+        ENET_VERSION_MAJOR as u8,
+        ENET_VERSION_MINOR as u8,
+        ENET_VERSION_PATCH as u8,
+    );
+
+    /// Creates a version from its components.
+    pub const fn new(major: u8, minor: u8, patch: u8) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Splits a version as returned by `linked_version` into its components.
+    pub fn from_raw(version: EnetVersion) -> Version {
+        Version::new((version >> 16) as u8, (version >> 8) as u8, version as u8)
+    }
+
+    /// Combines the components of this version like `ENET_VERSION_CREATE`.
+    pub fn to_raw(self) -> EnetVersion {
+        (EnetVersion::from(self.major) << 16)
+            | (EnetVersion::from(self.minor) << 8)
+            | EnetVersion::from(self.patch)
+    }
+}
+
+/// Renders the version as `major.minor.patch`.
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
 impl Drop for EnetKeepAlive {
     fn drop(&mut self) {
-        match ENET_STATUS.compare_exchange(
-            ENET_INITIALIZED,
-            ENET_DEINITIALIZED,
-            Ordering::SeqCst,
-            Ordering::SeqCst,
-        ) {
-            Ok(_) => (),
-            Err(other) => panic!(
-                "enet-rs internal error; unexpected value in ENET_STATUS (drop): {}",
-                other
-            ),
-        };
-
-        unsafe {
-            enet_deinitialize();
+        let mut refs = ENET_REFS.lock().unwrap_or_else(|err| err.into_inner());
+        *refs -= 1;
+        if *refs == 0 {
+            unsafe {
+                enet_deinitialize();
+            }
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+        net::Ipv4Addr,
+    };
+
+    use super::{
+        test_util::{self, service_until},
+        Address, BandwidthLimit, ChannelLimit, Enet, EventType, Host, HostCreateError, PeerID,
+        Version,
+    };
+
+    lazy_static! {
+        pub(crate) static ref ENET: Enet = test_util::enet();
+    }
+
+    /// Tracks the bytes allocated by each thread, to detect leaks of buffers passed to ENet, and
+    /// the number of allocations, to detect allocations in hot paths.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn track(size: isize) {
+        // Ignore allocations during thread teardown.
+        let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + size));
+        if size > 0 {
+            let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        }
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            track(layout.size() as isize);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            track(-(layout.size() as isize));
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    pub(crate) fn allocated() -> isize {
+        ALLOCATED.with(|allocated| allocated.get())
+    }
+
+    pub(crate) fn allocations() -> usize {
+        ALLOCATIONS.with(|allocations| allocations.get())
+    }
+
+    /// The `user_data` the client of `connected_pair` connects with.
+    pub(crate) const CONNECT_DATA: u32 = 42;
+
+    /// Creates a server and a client `Host` on localhost and connects them.
+    ///
+    /// Returns the server, the `PeerID` of the client on the server, the client and the `PeerID`
+    /// of the server on the client.
+    pub(crate) fn connected_pair<T>() -> (Host<T>, PeerID, Host<T>, PeerID) {
+        test_util::connected_pair(CONNECT_DATA)
+    }
+
+    #[test]
+    fn test_create_host_invalid_config() {
+        let create = |max_peer_count, max_channel_count, incoming_bandwidth| {
+            ENET.create_host::<()>(
+                None,
+                max_peer_count,
+                max_channel_count,
+                incoming_bandwidth,
+                BandwidthLimit::Unlimited,
+            )
+            .map(drop)
+        };
+        let invalid_field = |res: Result<(), HostCreateError>| match res {
+            Err(HostCreateError::InvalidConfig { field, .. }) => Some(field),
+            _ => None,
+        };
+
+        let unlimited = BandwidthLimit::Unlimited;
+        assert_eq!(
+            invalid_field(create(0, ChannelLimit::Maximum, unlimited)),
+            Some("max_peer_count")
+        );
+        assert_eq!(
+            invalid_field(create(4096, ChannelLimit::Maximum, unlimited)),
+            Some("max_peer_count")
+        );
+        assert_eq!(
+            invalid_field(create(1, ChannelLimit::Limited(0), unlimited)),
+            Some("max_channel_count")
+        );
+        assert_eq!(
+            invalid_field(create(1, ChannelLimit::Limited(256), unlimited)),
+            Some("max_channel_count")
+        );
+        assert_eq!(
+            invalid_field(create(1, ChannelLimit::Maximum, BandwidthLimit::Limited(0))),
+            Some("incoming_bandwidth")
+        );
+        assert!(create(4095, ChannelLimit::Limited(255), BandwidthLimit::Limited(1)).is_ok());
+    }
+
+    #[test]
+    fn test_fixed_source_port() {
+        let create_host = |ip, port| {
+            ENET.create_host::<()>(
+                Some(&Address::new(ip, port)),
+                1,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+            .unwrap()
+        };
+
+        // Nothing listens on the port of a destroyed host.
+        let local_port = create_host(Ipv4Addr::LOCALHOST, 0).address().port();
+
+        let mut client = create_host(Ipv4Addr::UNSPECIFIED, local_port);
+        let mut server = create_host(Ipv4Addr::LOCALHOST, 0);
+        client.connect(&server.address(), 1, 0).unwrap();
+
+        let client_address = service_until(&mut [&mut client, &mut server], |host, event| {
+            match event.r#type() {
+                EventType::Connect { .. } if host == 1 => Some(event.peer_address()),
+                _ => None,
+            }
+        });
+        assert_eq!(client_address.port(), local_port);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_host_from_socket() {
+        use std::net::{Ipv6Addr, SocketAddr, UdpSocket};
+
+        let create = |socket| {
+            ENET.create_host_from_socket::<()>(
+                socket,
+                1,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+        };
+
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = socket.local_addr().unwrap();
+        let mut server = create(socket).unwrap();
+        assert_eq!(SocketAddr::from(server.address()), address);
+
+        let mut client = ENET
+            .create_host::<()>(
+                None,
+                1,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+            .unwrap();
+        test_util::connect(&mut server, &mut client, 0);
+
+        // The socket is closed with the host.
+        drop(server);
+        UdpSocket::bind(address).unwrap();
+
+        let socket = UdpSocket::bind((Ipv6Addr::LOCALHOST, 0)).unwrap();
+        assert!(matches!(
+            create(socket),
+            Err(HostCreateError::InvalidConfig {
+                field: "socket",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_version() {
+        let version = Version::new(1, 3, 17);
+        assert_eq!(version.to_raw(), 0x01_03_11);
+        assert_eq!(Version::from_raw(0x01_03_11), version);
+        assert_eq!(version.to_string(), "1.3.17");
+        assert!(Version::new(1, 4, 0) > version);
+        assert!(Version::new(1, 3, 9) < version);
+
+        // This crate links the vendored ENet statically.
+        assert_eq!(Enet::linked_version(), Version::HEADERS);
+    }
+
+    #[test]
+    fn test_enet_new() {
+        let create_host = |enet: &Enet| {
+            enet.create_host::<()>(
+                None,
+                1,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+            .unwrap()
+        };
+
+        // Other tests share `ENET`, so ENet may or may not be initialized already.
+        let first = Enet::new().unwrap();
+        let second = Enet::new().unwrap();
+        let host = create_host(&first);
+        drop(first);
+
+        // The host and `second` keep ENet initialized.
+        let third = Enet::new().unwrap();
+        drop(second);
+        drop(host);
+        create_host(&third);
+        drop(third);
+
+        let _ = *ENET;
+        let fourth = Enet::new().unwrap();
+        drop(fourth);
+        create_host(&ENET);
+    }
+}
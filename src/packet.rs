@@ -1,6 +1,9 @@
+use std::ops::{BitAnd, BitOr, BitOrAssign};
+
 use enet_sys::{
     enet_packet_create, enet_packet_destroy, ENetPacket,
     _ENetPacketFlag_ENET_PACKET_FLAG_NO_ALLOCATE, _ENetPacketFlag_ENET_PACKET_FLAG_RELIABLE,
+    _ENetPacketFlag_ENET_PACKET_FLAG_SENT, _ENetPacketFlag_ENET_PACKET_FLAG_UNRELIABLE_FRAGMENT,
     _ENetPacketFlag_ENET_PACKET_FLAG_UNSEQUENCED,
 };
 
@@ -12,6 +15,13 @@ pub struct Packet {
     inner: *mut ENetPacket,
 }
 
+/// A borrowed view of a `Packet`, e.g. of a received packet that is still owned by its `Event`
+/// (see `Event::packet`).
+#[derive(Debug, Clone, Copy)]
+pub struct PacketRef<'a> {
+    packet: &'a Packet,
+}
+
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
 /// Mode that can be set when transmitting a packet.
 ///
@@ -45,6 +55,16 @@ impl PacketMode {
         }
     }
 
+    fn from_sys_flags(flags: u32) -> PacketMode {
+        if flags & _ENetPacketFlag_ENET_PACKET_FLAG_RELIABLE as u32 != 0 {
+            PacketMode::ReliableSequenced
+        } else if flags & _ENetPacketFlag_ENET_PACKET_FLAG_UNSEQUENCED as u32 != 0 {
+            PacketMode::UnreliableUnsequenced
+        } else {
+            PacketMode::UnreliableSequenced
+        }
+    }
+
     fn to_sys_flags(self) -> u32 {
         match self {
             PacketMode::UnreliableSequenced => 0,
@@ -56,6 +76,142 @@ impl PacketMode {
     }
 }
 
+/// The flags of an ENet packet (see `Packet::flags` and `Packet::with_flags`).
+///
+/// Flags are combined with `|`, and tested with `PacketFlags::contains`.
+///
+/// ```
+/// # use enet::PacketFlags;
+/// let flags = PacketFlags::RELIABLE | PacketFlags::NO_ALLOCATE;
+/// assert!(flags.contains(PacketFlags::RELIABLE));
+/// assert!(!flags.contains(PacketFlags::RELIABLE | PacketFlags::UNSEQUENCED));
+/// assert_eq!(flags & PacketFlags::NO_ALLOCATE, PacketFlags::NO_ALLOCATE);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PacketFlags(u32);
+
+impl PacketFlags {
+    /// The packet is sent reliably, see `PacketMode::ReliableSequenced`.
+    pub const RELIABLE: PacketFlags = PacketFlags(_ENetPacketFlag_ENET_PACKET_FLAG_RELIABLE as u32);
+    /// The packet is sent unsequenced, see `PacketMode::UnreliableUnsequenced`.
+    pub const UNSEQUENCED: PacketFlags =
+        PacketFlags(_ENetPacketFlag_ENET_PACKET_FLAG_UNSEQUENCED as u32);
+    /// The data of the packet is not allocated by ENet, but supplied by its creator, who has to
+    /// free it in the packet's `freeCallback`.
+    pub const NO_ALLOCATE: PacketFlags =
+        PacketFlags(_ENetPacketFlag_ENET_PACKET_FLAG_NO_ALLOCATE as u32);
+    /// Fragments of the unreliable packet are sent unreliably too, instead of reliably.
+    pub const UNRELIABLE_FRAGMENT: PacketFlags =
+        PacketFlags(_ENetPacketFlag_ENET_PACKET_FLAG_UNRELIABLE_FRAGMENT as u32);
+    /// The packet has been sent from all queues it was entered into. Set by ENet.
+    pub const SENT: PacketFlags = PacketFlags(_ENetPacketFlag_ENET_PACKET_FLAG_SENT as u32);
+
+    const ALL: PacketFlags = PacketFlags(
+        PacketFlags::RELIABLE.0
+            | PacketFlags::UNSEQUENCED.0
+            | PacketFlags::NO_ALLOCATE.0
+            | PacketFlags::UNRELIABLE_FRAGMENT.0
+            | PacketFlags::SENT.0,
+    );
+
+    /// Returns flags with no flag set.
+    pub const fn empty() -> PacketFlags {
+        PacketFlags(0)
+    }
+
+    /// Returns the flags as ENet's bit mask.
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns whether no flag is set.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns whether all flags of `other` are set.
+    pub const fn contains(self, other: PacketFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Checks that ENet handles a packet with these flags as they say.
+    ///
+    /// ENet silently ignores some combinations: reliable packets are never sent unsequenced, and
+    /// their fragments are never sent unreliably. `SENT` describes the state of a packet, so it
+    /// can't be chosen when creating one, and unknown bits are rejected as well.
+    pub fn validate(self) -> Result<(), PacketFlagsError> {
+        if self.0 & !PacketFlags::ALL.0 != 0 {
+            return Err(PacketFlagsError::Unknown(self.0 & !PacketFlags::ALL.0));
+        }
+        if self.contains(PacketFlags::SENT) {
+            return Err(PacketFlagsError::Sent);
+        }
+        if self.contains(PacketFlags::RELIABLE | PacketFlags::UNSEQUENCED) {
+            return Err(PacketFlagsError::ReliableUnsequenced);
+        }
+        if self.contains(PacketFlags::RELIABLE | PacketFlags::UNRELIABLE_FRAGMENT) {
+            return Err(PacketFlagsError::ReliableUnreliableFragment);
+        }
+
+        Ok(())
+    }
+}
+
+impl BitOr for PacketFlags {
+    type Output = PacketFlags;
+
+    fn bitor(self, rhs: PacketFlags) -> PacketFlags {
+        PacketFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for PacketFlags {
+    fn bitor_assign(&mut self, rhs: PacketFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for PacketFlags {
+    type Output = PacketFlags;
+
+    fn bitand(self, rhs: PacketFlags) -> PacketFlags {
+        PacketFlags(self.0 & rhs.0)
+    }
+}
+
+impl From<PacketMode> for PacketFlags {
+    fn from(mode: PacketMode) -> PacketFlags {
+        PacketFlags(mode.to_sys_flags())
+    }
+}
+
+/// An error that can occur when creating a packet with `Packet::with_flags` or
+/// `Packet::try_from_raw`.
+#[derive(thiserror::Error, Debug)]
+pub enum PacketFlagsError {
+    /// Bits were set that aren't ENet packet flags, contains the unknown bits.
+    #[error("unknown packet flags {:#x}", .0)]
+    Unknown(u32),
+    /// `PacketFlags::SENT` was set, which only ENet may set.
+    #[error("the `SENT` flag is set by ENet and can't be chosen")]
+    Sent,
+    /// `PacketFlags::RELIABLE` and `PacketFlags::UNSEQUENCED` were combined, which ENet sends
+    /// reliably and sequenced.
+    #[error("ENet does not support reliable but unsequenced packets")]
+    ReliableUnsequenced,
+    /// `PacketFlags::RELIABLE` and `PacketFlags::UNRELIABLE_FRAGMENT` were combined, which ENet
+    /// sends with reliable fragments.
+    #[error("reliable packets can't have unreliable fragments")]
+    ReliableUnreliableFragment,
+    /// `PacketFlags::NO_ALLOCATE` was set on a packet without a `freeCallback`, so the packet
+    /// doesn't own its data.
+    #[error("the data of a `NO_ALLOCATE` packet without a free callback isn't owned by it")]
+    UnownedData,
+    /// ENet failed to allocate the packet.
+    #[error(transparent)]
+    Enet(#[from] Error),
+}
+
 impl Packet {
     /// Creates a new Packet with optional reliability settings.
     ///
@@ -91,37 +247,296 @@ impl Packet {
         Ok(Packet::from_sys_packet(res))
     }
 
+    /// Creates a new Packet with the given flags, e.g. to send fragments of a large unreliable
+    /// packet unreliably.
+    ///
+    /// Like with `Packet::new`, the data is moved into the packet without copy, so the packet
+    /// always owns its data and `PacketFlags::NO_ALLOCATE` is set regardless of `flags`. Flags
+    /// that ENet would ignore are rejected, see `PacketFlags::validate`.
+    pub fn with_flags(data: Vec<u8>, flags: PacketFlags) -> Result<Packet, PacketFlagsError> {
+        flags.validate()?;
+
+        let packet = Packet::new(data, PacketMode::UnreliableSequenced)?;
+        unsafe {
+            (*packet.inner).flags |= flags.bits();
+        }
+
+        Ok(packet)
+    }
+
+    /// Creates a new Packet from several parts, e.g. a header and a body.
+    ///
+    /// The data is allocated by ENet once, and the parts are copied into it one after another, so
+    /// they don't have to be concatenated first.
+    pub fn from_slices(parts: &[&[u8]], mode: PacketMode) -> Result<Packet, Error> {
+        let len: usize = parts.iter().map(|part| part.len()).sum();
+
+        let res = unsafe {
+            enet_packet_create(
+                // ENet only allocates the data without initializing it if no data is passed.
+                std::ptr::null(),
+                len.try_into()
+                    .expect("packet data too long for ENet (`size_t`)"),
+                mode.to_sys_flags(),
+            )
+        };
+
+        if res.is_null() {
+            return Err(Error(0));
+        }
+
+        let packet = Packet::from_sys_packet(res);
+        let mut offset = 0;
+        for part in parts {
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    part.as_ptr(),
+                    (*packet.inner).data.add(offset),
+                    part.len(),
+                );
+            }
+            offset += part.len();
+        }
+
+        Ok(packet)
+    }
+
+    /// Wraps a packet returned by ENet, taking ownership of it.
+    ///
+    /// All other methods of `Packet` dereference `inner`, so it has to be valid and non-null.
     pub(crate) fn from_sys_packet(inner: *mut ENetPacket) -> Packet {
+        debug_assert!(!inner.is_null(), "ENet returned a null packet");
         Packet { inner }
     }
 
-    /// Does NOT run this `Packet`'s destructor.
-    pub(crate) fn into_inner(self) -> *mut ENetPacket {
+    /// Creates a `Packet` from a raw ENet packet, taking ownership of it.
+    ///
+    /// # Safety
+    /// `inner` has to be a valid, non-null packet, e.g. created by `enet_packet_create` or
+    /// received through `enet_peer_receive`. Unless ENet still references it, the returned
+    /// `Packet` destroys it with `enet_packet_destroy` when dropped, so it must not be destroyed or
+    /// sent by anyone else.
+    pub unsafe fn from_raw(inner: *mut ENetPacket) -> Packet {
+        Packet::from_sys_packet(inner)
+    }
+
+    /// Like `Packet::from_raw`, but checks that the flags of `inner` are valid first (see
+    /// `PacketFlags::validate`, `SENT` is allowed here), and that it owns its data.
+    ///
+    /// A `NO_ALLOCATE` packet only owns its data if its `freeCallback` frees it. Without one,
+    /// the data is still owned by someone else, who might free it while the `Packet` uses it.
+    /// On error, ownership of `inner` stays with the caller.
+    ///
+    /// # Safety
+    /// The same as for `Packet::from_raw`.
+    pub unsafe fn try_from_raw(inner: *mut ENetPacket) -> Result<Packet, PacketFlagsError> {
+        let flags = PacketFlags((*inner).flags);
+        PacketFlags(flags.0 & !PacketFlags::SENT.0).validate()?;
+        if flags.contains(PacketFlags::NO_ALLOCATE) && (*inner).freeCallback.is_none() {
+            return Err(PacketFlagsError::UnownedData);
+        }
+
+        Ok(Packet::from_sys_packet(inner))
+    }
+
+    /// Returns the raw ENet packet of this `Packet`.
+    ///
+    /// The packet is still owned by this `Packet`, so it must not be destroyed or sent.
+    pub fn as_raw(&self) -> *mut ENetPacket {
+        self.inner
+    }
+
+    /// Returns the raw ENet packet of this `Packet`, giving up ownership.
+    ///
+    /// Does NOT run this `Packet`'s destructor, so the caller is responsible for destroying or
+    /// sending the packet.
+    pub fn into_raw(self) -> *mut ENetPacket {
         let res = self.inner;
         std::mem::forget(self);
         res
     }
 
+    /// Returns the mode this packet will be sent with.
+    ///
+    /// For received packets, this is the mode the packet was sent with.
+    pub fn mode(&self) -> PacketMode {
+        PacketMode::from_sys_flags(unsafe { (*self.inner).flags })
+    }
+
+    /// Returns the flags of this packet.
+    pub fn flags(&self) -> PacketFlags {
+        PacketFlags(unsafe { (*self.inner).flags })
+    }
+
+    /// Changes the mode this packet will be sent with.
+    ///
+    /// As sending a packet consumes it, the mode can only be changed before the packet is sent.
+    pub fn set_mode(&mut self, mode: PacketMode) {
+        const MODE_FLAGS: u32 = _ENetPacketFlag_ENET_PACKET_FLAG_RELIABLE as u32
+            | _ENetPacketFlag_ENET_PACKET_FLAG_UNSEQUENCED as u32;
+
+        unsafe {
+            (*self.inner).flags = ((*self.inner).flags & !MODE_FLAGS) | mode.to_sys_flags();
+        }
+    }
+
     /// Returns a reference to the bytes inside this packet.
+    ///
+    /// The bytes live in an allocation of their own, which is neither moved, resized nor freed
+    /// while this `Packet` is alive: ENet doesn't keep a reference to received packets, and never
+    /// modifies the data of packets it sends. So they stay at the same address even when the
+    /// `Packet` is moved or its `Host` is serviced, which makes it safe to parse them in place,
+    /// e.g. with zero-copy parsers that keep pointers into the data until the `Packet` is dropped.
+    /// The same holds for the data of `Event::packet`, for as long as the `Event` is alive.
     pub fn data(&self) -> &[u8] {
+        unsafe { sys_packet_data(self.inner) }
+    }
+
+    /// Returns the first `N` bytes of this packet, or None if it is shorter.
+    ///
+    /// This borrows the bytes without copying the packet, e.g. to route on a message tag before
+    /// handing the whole packet to a handler.
+    ///
+    /// ```
+    /// # use enet::{Packet, PacketMode};
+    /// let packet = Packet::new(vec![1, 0, 42, 42], PacketMode::ReliableSequenced).unwrap();
+    /// assert_eq!(packet.header::<2>(), Some(&[1, 0]));
+    /// assert_eq!(packet.peek_u16_le(), Some(1));
+    /// assert_eq!(packet.header::<5>(), None);
+    /// ```
+    pub fn header<const N: usize>(&self) -> Option<&[u8; N]> {
+        self.data().first_chunk()
+    }
+
+    /// Returns the first byte of this packet, or None if it is empty.
+    pub fn peek_u8(&self) -> Option<u8> {
+        self.data().first().copied()
+    }
+
+    /// Returns the first 2 bytes of this packet as a little-endian `u16`, or None if it is
+    /// shorter.
+    pub fn peek_u16_le(&self) -> Option<u16> {
+        self.header().copied().map(u16::from_le_bytes)
+    }
+
+    /// Returns the first 4 bytes of this packet as a little-endian `u32`, or None if it is
+    /// shorter.
+    ///
+    /// This is also the length prefix of a frame written with `write_frame`.
+    pub fn peek_u32_le(&self) -> Option<u32> {
+        self.header().copied().map(u32::from_le_bytes)
+    }
+
+    /// Creates an independent copy of this packet, with the same bytes and flags.
+    ///
+    /// The bytes are copied into a newly allocated ENet packet, so the copy can be stored, changed
+    /// or sent independently of this packet. This is deliberately not `Clone`: ENet packets are
+    /// reference counted so that several peers can share one packet without copying its data, and
+    /// a copy made by this method never shares anything with the original.
+    pub fn deep_copy(&self) -> Packet {
+        // Whether the data is owned and whether the packet was sent don't carry over to the copy.
+        const COPIED_FLAGS: u32 = !(_ENetPacketFlag_ENET_PACKET_FLAG_NO_ALLOCATE as u32
+            | _ENetPacketFlag_ENET_PACKET_FLAG_SENT as u32);
+
+        let copy =
+            Packet::new(self.data().to_vec(), self.mode()).expect("failed to allocate packet copy");
         unsafe {
-            std::slice::from_raw_parts(
-                (*self.inner).data,
-                (*self.inner)
-                    .dataLength
-                    .try_into()
-                    // this can only happen when a too long packet is received on a 32-bit system I
-                    // think
-                    .expect("packet data too long for an `usize`"),
-            )
+            (*copy.inner).flags |= (*self.inner).flags & COPIED_FLAGS;
         }
+
+        copy
+    }
+}
+
+impl<'a> PacketRef<'a> {
+    /// Returns a reference to the bytes inside the packet.
+    pub fn data(&self) -> &'a [u8] {
+        self.packet.data()
+    }
+
+    /// Returns the first `N` bytes of the packet, see `Packet::header`.
+    pub fn header<const N: usize>(&self) -> Option<&'a [u8; N]> {
+        self.packet.header()
+    }
+
+    /// Returns the first byte of the packet, see `Packet::peek_u8`.
+    pub fn peek_u8(&self) -> Option<u8> {
+        self.packet.peek_u8()
+    }
+
+    /// Returns the first 2 bytes of the packet as a little-endian `u16`, see
+    /// `Packet::peek_u16_le`.
+    pub fn peek_u16_le(&self) -> Option<u16> {
+        self.packet.peek_u16_le()
+    }
+
+    /// Returns the first 4 bytes of the packet as a little-endian `u32`, see
+    /// `Packet::peek_u32_le`.
+    pub fn peek_u32_le(&self) -> Option<u32> {
+        self.packet.peek_u32_le()
+    }
+
+    /// Returns the number of bytes in the packet.
+    pub fn len(&self) -> usize {
+        self.data().len()
+    }
+
+    /// Returns whether the packet contains no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.data().is_empty()
+    }
+
+    /// Returns the mode the packet was (or will be) sent with.
+    pub fn mode(&self) -> PacketMode {
+        self.packet.mode()
+    }
+
+    /// Returns the flags of the packet, see `Packet::flags`.
+    pub fn flags(&self) -> PacketFlags {
+        self.packet.flags()
+    }
+}
+
+impl<'a> From<&'a Packet> for PacketRef<'a> {
+    fn from(packet: &'a Packet) -> PacketRef<'a> {
+        PacketRef { packet }
+    }
+}
+
+/// Returns the bytes of a raw ENet packet.
+///
+/// The caller has to make sure that `packet` is valid and non-null for the returned lifetime.
+pub(crate) unsafe fn sys_packet_data<'a>(packet: *const ENetPacket) -> &'a [u8] {
+    debug_assert!(!packet.is_null(), "packet must not be null");
+
+    // ENet doesn't allocate any data for empty packets, but `from_raw_parts` requires a non-null
+    // pointer even for empty slices.
+    if (*packet).data.is_null() {
+        return &[];
     }
+
+    std::slice::from_raw_parts(
+        (*packet).data,
+        (*packet)
+            .dataLength
+            .try_into()
+            // this can only happen when a too long packet is received on a 32-bit system I
+            // think
+            .expect("packet data too long for an `usize`"),
+    )
 }
 
+/// Destroys the packet, unless it is still queued for a peer.
+///
+/// A packet queued with `enet_peer_send` is referenced by the outgoing commands of the peer, and
+/// ENet destroys it once the last reference is gone. Leaving it alone here means a `Packet` can be
+/// dropped at any point of sending it, e.g. while unwinding from a panicking hook.
 impl Drop for Packet {
     fn drop(&mut self) {
         unsafe {
-            enet_packet_destroy(self.inner);
+            if (*self.inner).referenceCount == 0 {
+                enet_packet_destroy(self.inner);
+            }
         }
     }
 }
@@ -133,3 +548,285 @@ unsafe extern "C" fn packet_free_callback(packet: *mut ENetPacket) {
         (*packet).userData as usize,
     ));
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, time::Duration};
+
+    use enet_sys::{
+        enet_packet_create, enet_packet_destroy, ENetPacket,
+        _ENetPacketFlag_ENET_PACKET_FLAG_NO_ALLOCATE,
+    };
+
+    use super::{
+        packet_free_callback, Packet, PacketFlags, PacketFlagsError, PacketMode, PacketRef,
+    };
+    use crate::{tests::connected_pair, EventType};
+
+    thread_local! {
+        static DESTROYED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe extern "C" fn counting_free_callback(packet: *mut ENetPacket) {
+        DESTROYED.with(|destroyed| destroyed.set(destroyed.get() + 1));
+
+        // Packets created through `Packet::new` own their data.
+        if (*packet).flags & _ENetPacketFlag_ENET_PACKET_FLAG_NO_ALLOCATE as u32 != 0 {
+            packet_free_callback(packet);
+        }
+    }
+
+    fn count_destruction(packet: &Packet) {
+        unsafe {
+            (*packet.inner).freeCallback = Some(counting_free_callback);
+        }
+    }
+
+    fn destroyed() -> usize {
+        DESTROYED.with(|destroyed| destroyed.get())
+    }
+
+    #[test]
+    fn test_set_mode() {
+        let mut packet = Packet::new(vec![1, 2, 3], PacketMode::ReliableSequenced).unwrap();
+        assert_eq!(packet.mode(), PacketMode::ReliableSequenced);
+
+        packet.set_mode(PacketMode::UnreliableUnsequenced);
+        assert_eq!(packet.mode(), PacketMode::UnreliableUnsequenced);
+        assert_ne!(
+            unsafe { (*packet.inner).flags } & _ENetPacketFlag_ENET_PACKET_FLAG_NO_ALLOCATE as u32,
+            0
+        );
+
+        packet.set_mode(PacketMode::UnreliableSequenced);
+        assert_eq!(packet.mode(), PacketMode::UnreliableSequenced);
+    }
+
+    #[test]
+    fn test_header() {
+        let packet = Packet::new(vec![7, 1, 0, 0, 9], PacketMode::ReliableSequenced).unwrap();
+        assert_eq!(packet.header::<0>(), Some(&[]));
+        assert_eq!(packet.header::<5>(), Some(&[7, 1, 0, 0, 9]));
+        assert_eq!(packet.header::<6>(), None);
+        assert_eq!(packet.peek_u8(), Some(7));
+        assert_eq!(packet.peek_u16_le(), Some(0x0107));
+        assert_eq!(packet.peek_u32_le(), Some(0x0107));
+
+        let packet_ref = PacketRef::from(&packet);
+        assert_eq!(packet_ref.header::<2>(), Some(&[7, 1]));
+        assert_eq!(packet_ref.peek_u32_le(), packet.peek_u32_le());
+
+        let empty = Packet::new(vec![], PacketMode::ReliableSequenced).unwrap();
+        assert_eq!(empty.peek_u8(), None);
+        assert_eq!(empty.peek_u16_le(), None);
+        assert_eq!(PacketRef::from(&empty).peek_u8(), None);
+    }
+
+    #[test]
+    fn test_with_flags() {
+        let flags = PacketFlags::UNRELIABLE_FRAGMENT;
+        let packet = Packet::with_flags(vec![1, 2, 3], flags).unwrap();
+        assert_eq!(packet.data(), &[1, 2, 3]);
+        assert_eq!(packet.mode(), PacketMode::UnreliableSequenced);
+        assert!(packet
+            .flags()
+            .contains(PacketFlags::UNRELIABLE_FRAGMENT | PacketFlags::NO_ALLOCATE));
+        assert_eq!(PacketRef::from(&packet).flags(), packet.flags());
+        assert_eq!(
+            PacketFlags::from(PacketMode::ReliableSequenced),
+            PacketFlags::RELIABLE
+        );
+
+        for (flags, expected) in [
+            (
+                PacketFlags::RELIABLE | PacketFlags::UNSEQUENCED,
+                PacketFlagsError::ReliableUnsequenced,
+            ),
+            (
+                PacketFlags::RELIABLE | PacketFlags::UNRELIABLE_FRAGMENT,
+                PacketFlagsError::ReliableUnreliableFragment,
+            ),
+            (PacketFlags::SENT, PacketFlagsError::Sent),
+            (
+                PacketFlags::RELIABLE | PacketFlags(1 << 5),
+                PacketFlagsError::Unknown(1 << 5),
+            ),
+        ] {
+            let err = Packet::with_flags(vec![1], flags).unwrap_err();
+            assert_eq!(err.to_string(), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn test_try_from_raw() {
+        let data = [1_u8, 2, 3];
+
+        // The data belongs to this test, so the packet must not take ownership of it.
+        let raw = unsafe {
+            enet_packet_create(
+                data.as_ptr() as *const _,
+                data.len(),
+                _ENetPacketFlag_ENET_PACKET_FLAG_NO_ALLOCATE as u32,
+            )
+        };
+        assert!(matches!(
+            unsafe { Packet::try_from_raw(raw) },
+            Err(PacketFlagsError::UnownedData)
+        ));
+        unsafe { enet_packet_destroy(raw) };
+
+        let raw = Packet::new(data.to_vec(), PacketMode::ReliableSequenced)
+            .unwrap()
+            .into_raw();
+        let packet = unsafe { Packet::try_from_raw(raw) }.unwrap();
+        assert_eq!(packet.data(), &data);
+    }
+
+    #[test]
+    fn test_deep_copy() {
+        let mut packet = Packet::new(vec![1, 2, 3], PacketMode::UnreliableUnsequenced).unwrap();
+        let copy = packet.deep_copy();
+        assert_eq!(copy.data(), packet.data());
+        assert_eq!(copy.mode(), PacketMode::UnreliableUnsequenced);
+        assert_ne!(copy.data().as_ptr(), packet.data().as_ptr());
+
+        packet.set_mode(PacketMode::ReliableSequenced);
+        assert_eq!(copy.mode(), PacketMode::UnreliableUnsequenced);
+
+        count_destruction(&copy);
+        let before = destroyed();
+        drop(packet);
+        assert_eq!(destroyed(), before);
+        drop(copy);
+        assert_eq!(destroyed(), before + 1);
+    }
+
+    #[test]
+    fn test_from_slices() {
+        let header = [1, 2];
+        let body = vec![3, 4, 5];
+        let packet =
+            Packet::from_slices(&[&header, &[], &body], PacketMode::UnreliableSequenced).unwrap();
+        assert_eq!(packet.data(), &[1, 2, 3, 4, 5]);
+        assert_eq!(packet.mode(), PacketMode::UnreliableSequenced);
+
+        let empty = Packet::from_slices(&[], PacketMode::ReliableSequenced).unwrap();
+        assert!(empty.data().is_empty());
+    }
+
+    #[test]
+    fn test_drop_unsent() {
+        let packet = Packet::new(vec![1, 2, 3], PacketMode::ReliableSequenced).unwrap();
+        count_destruction(&packet);
+
+        let before = destroyed();
+        drop(packet);
+        assert_eq!(destroyed(), before + 1);
+    }
+
+    #[test]
+    fn test_send_transfers_ownership() {
+        let (mut server, client_id, mut client, server_id) = connected_pair::<()>();
+
+        let packet = Packet::new(vec![1, 2, 3], PacketMode::ReliableSequenced).unwrap();
+        count_destruction(&packet);
+
+        let before = destroyed();
+        client
+            .peer_mut(server_id)
+            .unwrap()
+            .send_packet(packet, 0)
+            .unwrap();
+        assert_eq!(destroyed(), before);
+
+        // Sending on a non-existent channel fails, so the packet has to be destroyed right away.
+        let packet = Packet::new(vec![1, 2, 3], PacketMode::ReliableSequenced).unwrap();
+        count_destruction(&packet);
+        assert!(client
+            .peer_mut(server_id)
+            .unwrap()
+            .send_packet(packet, 200)
+            .is_err());
+        assert_eq!(destroyed(), before + 1);
+
+        client.flush();
+        let received = loop {
+            let event = server.service(Duration::from_millis(100)).unwrap();
+            if let Some(EventType::Receive { packet, .. }) = event.map(|event| event.take_type()) {
+                break packet;
+            }
+        };
+        assert_eq!(received.data(), &[1, 2, 3]);
+        count_destruction(&received);
+        drop(received);
+        assert_eq!(destroyed(), before + 2);
+
+        // The sent packet is kept until it has been acknowledged, dropping the host releases it.
+        drop(client);
+        assert_eq!(destroyed(), before + 3);
+
+        assert!(server.peer(client_id).is_some());
+    }
+
+    #[test]
+    fn test_data_address_stable() {
+        let (mut server, client_id, mut client, server_id) = connected_pair::<()>();
+
+        let mut receive = |data: Vec<u8>| {
+            client
+                .peer_mut(server_id)
+                .unwrap()
+                .send_owned(data, PacketMode::ReliableSequenced, 0)
+                .unwrap();
+            client.flush();
+            loop {
+                let event = server.service(Duration::from_millis(100)).unwrap();
+                if let Some(packet) = event.and_then(|event| event.take_packet()) {
+                    break packet;
+                }
+            }
+        };
+
+        let packet = receive(vec![7; 5000]);
+        let data = packet.data().as_ptr();
+
+        // Moving the packet and servicing the host, which receives and frees other packets, keeps
+        // the data in place.
+        let packet = Box::new(packet);
+        for len in [1, 100, 10000] {
+            drop(receive(vec![1; len]));
+        }
+        server
+            .peer_mut(client_id)
+            .unwrap()
+            .send_owned(vec![2; 3000], PacketMode::ReliableSequenced, 0)
+            .unwrap();
+        server.flush();
+
+        assert_eq!(packet.data().as_ptr(), data);
+        assert_eq!(packet.data(), &[7; 5000][..]);
+    }
+
+    #[test]
+    fn test_receive_empty() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+
+        client
+            .peer_mut(server_id)
+            .unwrap()
+            .send_packet(
+                Packet::new(vec![], PacketMode::ReliableSequenced).unwrap(),
+                0,
+            )
+            .unwrap();
+        client.flush();
+
+        let received = loop {
+            let event = server.service(Duration::from_millis(100)).unwrap();
+            if let Some(packet) = event.and_then(|event| event.take_packet()) {
+                break packet;
+            }
+        };
+        assert!(received.data().is_empty());
+    }
+}
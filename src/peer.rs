@@ -1,13 +1,18 @@
 use std::{
+    any::Any,
+    cmp::Reverse,
     fmt::{self, Debug, Formatter},
     marker::PhantomData,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use serde::{Serialize, Deserialize};
 
 use enet_sys::{
-    enet_peer_disconnect, enet_peer_disconnect_later, enet_peer_disconnect_now, enet_peer_receive,
-    enet_peer_reset, enet_peer_send, ENetPeer, _ENetPeerState,
+    enet_peer_disconnect, enet_peer_disconnect_later, enet_peer_disconnect_now, enet_peer_ping,
+    enet_peer_ping_interval, enet_peer_receive, enet_peer_reset, enet_peer_send,
+    enet_peer_throttle_configure, enet_peer_timeout, ENetChannel, ENetList, ENetListNode,
+    ENetOutgoingCommand, ENetPeer, ENET_PEER_DEFAULT_PACKET_THROTTLE,
+    ENET_PROTOCOL_MAXIMUM_FRAGMENT_COUNT, _ENetPeerState,
     _ENetPeerState_ENET_PEER_STATE_ACKNOWLEDGING_CONNECT,
     _ENetPeerState_ENET_PEER_STATE_ACKNOWLEDGING_DISCONNECT,
     _ENetPeerState_ENET_PEER_STATE_CONNECTED, _ENetPeerState_ENET_PEER_STATE_CONNECTING,
@@ -17,7 +22,10 @@ use enet_sys::{
     _ENetPeerState_ENET_PEER_STATE_DISCONNECT_LATER, _ENetPeerState_ENET_PEER_STATE_ZOMBIE,
 };
 
-use crate::{Address, Error, Packet};
+use crate::{
+    coalesce, host::HostHooks, Address, Direction, DisconnectError, Error, Packet, PacketMode,
+    PeerSendError,
+};
 
 /// This struct represents an endpoint in an ENet-connection.
 ///
@@ -37,6 +45,34 @@ pub struct Peer<T> {
 struct PeerData<T> {
     peer_generation: usize,
     user_data: Option<T>,
+    hooks: *mut HostHooks,
+    disconnect_pending: bool,
+    /// The `connectID` of the connection initiated by this host with `Host::connect_with`.
+    ///
+    /// A peer that ENet resets on its own keeps this until it is cleaned up, the ID tells a new
+    /// connection in the same slot apart.
+    outgoing: Option<u32>,
+    /// The messages queued with `Peer::queue`, coalesced per channel.
+    queued: Vec<(u8, Vec<u8>)>,
+    /// The channels with a non-default priority, see `Peer::set_channel_priority`.
+    channel_priorities: Vec<(u8, u8)>,
+    /// Whether a `Receive` event was delivered since the peer connected.
+    received: bool,
+}
+
+/// A ping sent with `Peer::ping`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ping {
+    /// The reliable sequence number of the ping command.
+    sequence: u16,
+    sent: Instant,
+}
+
+impl Ping {
+    /// Returns when the ping was queued.
+    pub fn sent(&self) -> Instant {
+        self.sent
+    }
 }
 
 /// A packet received directly from a `Peer`.
@@ -78,11 +114,54 @@ where
         unsafe { &mut *(inner as *mut _ as *mut Peer<T>) }
     }
 
+    /// Returns a `Peer` reference for a raw ENet peer.
+    ///
+    /// # Safety
+    /// `inner` has to be a valid, non-null peer of a `Host<T>` with the same `T`, as the data
+    /// associated with the peer is interpreted as data of type `T`. The returned reference must not
+    /// outlive that `Host`, and must not alias any other reference to the same peer.
+    pub unsafe fn from_raw(inner: *mut ENetPeer) -> &'a mut Peer<T> {
+        debug_assert!(!inner.is_null(), "peer must not be null");
+        Peer::new_mut(&mut *inner)
+    }
+
+    /// Returns the raw ENet peer of this `Peer`.
+    ///
+    /// The `data` field of the peer is managed by this crate and must not be modified.
+    pub fn as_raw(&self) -> *const ENetPeer {
+        &self.inner
+    }
+
+    /// Returns the raw ENet peer of this `Peer`.
+    ///
+    /// The `data` field of the peer is managed by this crate and must not be modified.
+    pub fn as_raw_mut(&mut self) -> *mut ENetPeer {
+        &mut self.inner
+    }
+
     /// Returns the address of this `Peer`.
     pub fn address(&self) -> Address {
         Address::from_enet_address(&self.inner.address)
     }
 
+    /// Returns the local address of the `Host` this `Peer` belongs to, see `Host::address`.
+    ///
+    /// A `Peer` can't give access to its `Host`, as that would alias the borrow of the `Peer`, so
+    /// host-level data that is useful while handling a `Peer` is exposed here instead.
+    pub fn host_address(&self) -> Address {
+        // Safety: The peers of a host are only reachable while the host is alive.
+        Address::from_enet_address(&unsafe { (*self.inner.host).address })
+    }
+
+    /// Returns the `user_data` the foreign host passed to `Host::connect` when connecting to this
+    /// host, the same value as in the `EventType::Connect` of this `Peer`.
+    ///
+    /// This is 0 for connections initiated by this host. Once a disconnect was received from the
+    /// foreign host, this returns the data of the disconnect instead.
+    pub fn connect_data(&self) -> u32 {
+        self.inner.eventData
+    }
+
     /// Returns the amout of channels allocated for this `Peer`.
     pub fn channel_count(&self) -> enet_sys::size_t {
         self.inner.channelCount
@@ -109,6 +188,12 @@ where
                 raw_data = Box::into_raw(Box::new(PeerData {
                     peer_generation: 0,
                     user_data: None,
+                    hooks: std::ptr::null_mut(),
+                    disconnect_pending: false,
+                    outgoing: None,
+                    queued: Vec::new(),
+                    channel_priorities: Vec::new(),
+                    received: false,
                 }));
                 self.inner.data = raw_data as *mut _;
             }
@@ -127,6 +212,59 @@ where
         }
     }
 
+    /// Stores a pointer to the `HostHooks` of the `Host` owning this `Peer`.
+    ///
+    /// The pointer has to stay valid for as long as the data of this `Peer` exists.
+    pub(crate) fn attach_hooks(&mut self, hooks: *mut HostHooks) {
+        self.raw_data_mut().hooks = hooks;
+    }
+
+    fn hooks_mut(&mut self) -> Option<&mut HostHooks> {
+        let hooks = self.raw_data_mut().hooks;
+
+        if hooks.is_null() {
+            None
+        } else {
+            Some(unsafe { &mut *hooks })
+        }
+    }
+
+    fn capture(&mut self, direction: Direction, channel_id: u8, data: &[u8]) {
+        let peer_id = self.id();
+        if let Some(hooks) = self.hooks_mut() {
+            hooks.capture(direction, peer_id, channel_id, data);
+        }
+    }
+
+    fn account_sent(&mut self, channel_id: u8, len: usize) {
+        let peer_id = self.id();
+        if let Some(hooks) = self.hooks_mut() {
+            hooks.account_sent(peer_id, channel_id, len);
+        }
+    }
+
+    fn accept_incoming(&mut self, channel_id: u8, data: &[u8]) -> bool {
+        let peer_id = self.id();
+        match self.hooks_mut() {
+            Some(hooks) => hooks.accept_incoming(peer_id, channel_id, data),
+            None => true,
+        }
+    }
+
+    pub(crate) fn id(&self) -> PeerID {
+        // We can do pointer arithmetic here to determine the offset of this Peer in the
+        // list of peers of its host, which is it's PeerID.
+        // ENet sets `host` for every peer when creating the host, and `Peer`s are only ever
+        // borrowed from the peers of a `Host`, so both pointers belong to the same allocation.
+        debug_assert!(!self.inner.host.is_null(), "peer without host");
+        let index =
+            unsafe { (&self.inner as *const ENetPeer).offset_from((*self.inner.host).peers) };
+        PeerID {
+            index,
+            generation: self.generation(),
+        }
+    }
+
     pub(crate) fn generation(&self) -> usize {
         if let Some(peer_data) = self.raw_data() {
             peer_data.peer_generation
@@ -139,10 +277,48 @@ where
     /// has been disconnected, including increasing the generation,
     /// as well as dropping the data associated with this peer.
     pub(crate) fn cleanup_after_disconnect(&mut self) {
-        self.raw_data_mut().peer_generation += 1;
+        let raw_data = self.raw_data_mut();
+        raw_data.peer_generation += 1;
+        raw_data.outgoing = None;
+        raw_data.queued.clear();
+        raw_data.channel_priorities.clear();
+        raw_data.received = false;
         self.take_data();
     }
 
+    /// Marks this `Peer` as disconnected by a `Disconnect` event, see `finish_disconnect`.
+    pub(crate) fn begin_disconnect(&mut self) {
+        self.raw_data_mut().disconnect_pending = true;
+    }
+
+    /// Marks the current connection of this `Peer` as created by `Host::connect_with`.
+    pub(crate) fn mark_outgoing(&mut self) {
+        self.raw_data_mut().outgoing = Some(self.inner.connectID);
+    }
+
+    /// Returns whether the current connection of this `Peer` was marked with `mark_outgoing`.
+    pub(crate) fn is_outgoing(&self) -> bool {
+        self.raw_data()
+            .is_some_and(|peer_data| peer_data.outgoing == Some(self.inner.connectID))
+    }
+
+    /// Marks a `Receive` event as delivered for this `Peer`, returns whether it is the first one
+    /// since the peer connected.
+    pub(crate) fn mark_received(&mut self) -> bool {
+        !std::mem::replace(&mut self.raw_data_mut().received, true)
+    }
+
+    /// Runs `cleanup_after_disconnect` once after `begin_disconnect` was called.
+    ///
+    /// Calling this again, or without a preceding `begin_disconnect`, does nothing.
+    pub(crate) fn finish_disconnect(&mut self) {
+        let raw_data = self.raw_data_mut();
+        if raw_data.disconnect_pending {
+            raw_data.disconnect_pending = false;
+            self.cleanup_after_disconnect();
+        }
+    }
+
     /// Returns a reference to the data associated with this `Peer`, if set.
     pub fn data(&self) -> Option<&T> {
         if let Some(peer_data) = self.raw_data() {
@@ -164,6 +340,22 @@ where
         self.raw_data_mut().user_data = Some(data);
     }
 
+    /// Sets the data associated with this `Peer`, and returns the data it replaced instead of
+    /// dropping it.
+    pub fn replace_data(&mut self, data: T) -> Option<T> {
+        self.raw_data_mut().user_data.replace(data)
+    }
+
+    /// Sets the data associated with this `Peer` to the value produced by `f`, replacing existing
+    /// data.
+    ///
+    /// `f` receives the address of this `Peer` and its connect data (see `Peer::connect_data`),
+    /// which makes this convenient to call when handling the `EventType::Connect` of the `Peer`.
+    pub fn set_data_with(&mut self, f: impl FnOnce(Address, u32) -> T) {
+        let data = f(self.address(), self.connect_data());
+        self.set_data(data);
+    }
+
     /// Take the data associated with this `Peer` out of it.
     /// No more data will be associated with this Peer after this call.
     pub fn take_data(&mut self) -> Option<T> {
@@ -185,13 +377,263 @@ where
         Duration::from_millis(self.inner.roundTripTime as u64)
     }
 
+    /// Returns the amount of reliable data in bytes that has been sent to this `Peer`, but not yet
+    /// acknowledged.
+    pub fn reliable_data_in_transit(&self) -> u32 {
+        self.inner.reliableDataInTransit
+    }
+
+    /// Returns the amount of data in bytes that is queued to be sent to this `Peer`, but hasn't
+    /// been sent yet.
+    ///
+    /// This includes unreliable data, and reliable data that is queued to be resent after it
+    /// wasn't acknowledged in time.
+    pub fn queued_outgoing_data(&self) -> usize {
+        unsafe {
+            outgoing_data_len(&self.inner.outgoingSendReliableCommands)
+                + outgoing_data_len(&self.inner.outgoingCommands)
+        }
+    }
+
+    /// Returns the amount of data in bytes that was received from this `Peer`, but hasn't been
+    /// delivered yet.
+    ///
+    /// This includes packets whose events are pending, reliable packets that wait for an earlier
+    /// packet that was lost, and packets whose fragments are still being reassembled. Once it
+    /// reaches `Host::maximum_waiting_data`, ENet drops further packets of this `Peer`, so a
+    /// `Peer` that keeps it high, e.g. by never completing its packets, ties up memory of this
+    /// host. See `Host::set_memory_pressure_handler`.
+    pub fn total_waiting_data(&self) -> usize {
+        self.inner.totalWaitingData
+    }
+
+    /// Returns the size in bytes of the reliable window of this `Peer`, as derived from the
+    /// bandwidth limits of both hosts during the connection.
+    ///
+    /// ENet stops sending reliable packets to this `Peer` while `reliable_data_in_transit` would
+    /// exceed this window, scaled by `packet_throttle`.
+    pub fn reliable_window(&self) -> u32 {
+        self.inner.windowSize
+    }
+
+    /// Returns the current packet throttle of this `Peer`, from 0 to 32 (the throttle scale).
+    pub fn packet_throttle(&self) -> u32 {
+        self.inner.packetThrottle
+    }
+
+    /// Returns the maximum transmission unit of this `Peer`, i.e. the maximum size of a datagram
+    /// sent to it, including ENet's headers.
+    ///
+    /// The MTU is negotiated during the handshake, and is final once the `EventType::Connect` of
+    /// this `Peer` has been received. ENet doesn't change it afterwards.
+    pub fn mtu(&self) -> u32 {
+        self.inner.mtu
+    }
+
+    /// Returns the largest packet size that can be sent to this `Peer` in a single datagram.
+    ///
+    /// This is the MTU minus ENet's protocol header, command header and checksum (if enabled, see
+    /// `Host::set_checksum_crc32`). Larger packets are split into fragments, which costs extra
+    /// overhead and, for unreliable packets, drops the whole packet if any fragment is lost.
+    pub fn max_payload_size(&self) -> usize {
+        // sizeof(ENetProtocolHeader) and sizeof(ENetProtocolSendFragment), see `enet_peer_send`.
+        const PROTOCOL_HEADER_SIZE: u32 = 4;
+        const SEND_FRAGMENT_SIZE: u32 = 24;
+        const CHECKSUM_SIZE: u32 = 4;
+
+        let mut overhead = PROTOCOL_HEADER_SIZE + SEND_FRAGMENT_SIZE;
+        if unsafe { (*self.inner.host).checksum.is_some() } {
+            overhead += CHECKSUM_SIZE;
+        }
+
+        self.mtu().saturating_sub(overhead) as usize
+    }
+
+    /// Returns the size in bytes of the largest packet that can be sent to this `Peer`.
+    ///
+    /// This is the maximum packet size of the `Host` (see `Host::set_maximum_packet_size`),
+    /// unless the packet would need more fragments than ENet supports at the current MTU. The
+    /// foreign host rejects packets larger than its own maximum packet size, so both hosts
+    /// should use the same limit.
+    pub fn max_packet_size(&self) -> usize {
+        let host_max = unsafe { (*self.inner.host).maximumPacketSize };
+        let fragment_max = self.max_payload_size() * ENET_PROTOCOL_MAXIMUM_FRAGMENT_COUNT as usize;
+
+        host_max.min(fragment_max)
+    }
+
+    /// Returns whether the reliable window of this `Peer` is full.
+    ///
+    /// While this is the case, ENet doesn't send any further reliable packets to this `Peer`, until
+    /// some of the data in transit has been acknowledged. Reliable packets sent in the meantime
+    /// just grow the outgoing queue, so a sender can use this to apply backpressure.
+    ///
+    /// This compares `reliable_data_in_transit` against `reliable_window` scaled by
+    /// `packet_throttle`, the same way ENet does before sending a full-sized fragment.
+    pub fn is_send_queue_saturated(&self) -> bool {
+        const PACKET_THROTTLE_SCALE: u64 = 32;
+
+        let mtu = self.inner.mtu as u64;
+        let window =
+            self.inner.windowSize as u64 * self.inner.packetThrottle as u64 / PACKET_THROTTLE_SCALE;
+
+        self.inner.reliableDataInTransit as u64 + mtu > window.max(mtu)
+    }
+
+    /// Returns the bitmask of reliable windows currently in use on the given channel, None if the
+    /// channel does not exist.
+    ///
+    /// Each channel splits its reliable sequence numbers in 16 windows of 4096 packets each.
+    pub fn used_reliable_windows(&self, channel_id: u8) -> Option<u16> {
+        self.channel(channel_id)
+            .map(|channel| channel.usedReliableWindows)
+    }
+
+    /// Returns the number of unacknowledged reliable packets in each of the 16 reliable windows of
+    /// the given channel, None if the channel does not exist.
+    pub fn reliable_windows(&self, channel_id: u8) -> Option<[u16; 16]> {
+        self.channel(channel_id)
+            .map(|channel| channel.reliableWindows)
+    }
+
+    fn channel(&self, channel_id: u8) -> Option<&ENetChannel> {
+        if (channel_id as usize) < self.inner.channelCount {
+            unsafe { Some(&*self.inner.channels.add(channel_id as usize)) }
+        } else {
+            None
+        }
+    }
+
+    /// Sets the timeout parameters of this `Peer`.
+    ///
+    /// Once a reliable packet to this `Peer` timed out, the `Peer` is disconnected.
+    pub fn set_timeout(&mut self, timeout: PeerTimeout) {
+        unsafe {
+            enet_peer_timeout(
+                &mut self.inner as *mut _,
+                timeout.limit,
+                duration_to_millis(timeout.minimum),
+                duration_to_millis(timeout.maximum),
+            );
+        }
+    }
+
+    /// Sends a ping to this `Peer`, to measure the round trip time on demand.
+    ///
+    /// The ping is sent with the next service (or `Host::flush`). Once it was acknowledged (see
+    /// `Peer::ping_acknowledged`), `Peer::mean_rtt` includes its round trip. Returns None if this
+    /// `Peer` is not connected. `Host::measure_rtt` sends a ping and waits for it.
+    pub fn ping(&mut self) -> Option<Ping> {
+        if self.state() != PeerState::Connected {
+            return None;
+        }
+
+        unsafe {
+            enet_peer_ping(&mut self.inner as *mut _);
+        }
+
+        // Commands without a channel, like pings, are numbered per peer.
+        Some(Ping {
+            sequence: self.inner.outgoingReliableSequenceNumber,
+            sent: Instant::now(),
+        })
+    }
+
+    /// Returns whether `ping` was acknowledged by the foreign host.
+    ///
+    /// Returns false if this `Peer` is no longer connected.
+    pub fn ping_acknowledged(&self, ping: &Ping) -> bool {
+        if self.state() != PeerState::Connected {
+            return false;
+        }
+
+        // Acknowledged commands are removed from the queues. Only commands without a channel are
+        // sent without a packet, and they share the sequence numbers of the ping.
+        let pending = |list: &ENetList| unsafe {
+            list_commands(list).any(|command| {
+                command.packet.is_null() && command.reliableSequenceNumber == ping.sequence
+            })
+        };
+        !pending(&self.inner.outgoingCommands) && !pending(&self.inner.sentReliableCommands)
+    }
+
+    /// Sets the interval at which pings are sent to this `Peer` while no other reliable packets
+    /// are sent. ENet's default is 500ms.
+    pub fn set_ping_interval(&mut self, interval: Duration) {
+        unsafe {
+            enet_peer_ping_interval(&mut self.inner as *mut _, duration_to_millis(interval));
+        }
+    }
+
+    /// Configures the throttle of this `Peer`, see `PeerThrottle`.
+    ///
+    /// The throttle parameters are also sent to the foreign host.
+    pub fn configure_throttle(&mut self, throttle: PeerThrottle) {
+        unsafe {
+            enet_peer_throttle_configure(
+                &mut self.inner as *mut _,
+                duration_to_millis(throttle.interval),
+                throttle.acceleration,
+                throttle.deceleration,
+            );
+        }
+    }
+
+    /// Applies all settings of `config` to this `Peer`.
+    pub fn apply_config(&mut self, config: &PeerConfig) {
+        if let Some(timeout) = config.timeout {
+            self.set_timeout(timeout);
+        }
+        if let Some(interval) = config.ping_interval {
+            self.set_ping_interval(interval);
+        }
+        if let Some(throttle) = config.throttle {
+            self.configure_throttle(throttle);
+        }
+    }
+
+    /// Resets the packet loss and throttle accounting of this `Peer`, so that measurements start
+    /// from a clean state, e.g. after the network conditions of the peer changed drastically.
+    ///
+    /// The packet loss statistics are cleared and the throttle is reset to its default (fully
+    /// open), both start a new epoch with the next service. The round trip time itself is kept, as
+    /// ENet derives its retransmission timeouts from it.
+    pub fn reset_statistics(&mut self) {
+        let peer = &mut self.inner;
+
+        // ENet starts a new epoch when it sees an epoch of 0, like for a new connection.
+        peer.packetLossEpoch = 0;
+        peer.packetsSent = 0;
+        peer.packetsLost = 0;
+        peer.packetLoss = 0;
+        peer.packetLossVariance = 0;
+
+        peer.packetThrottle = ENET_PEER_DEFAULT_PACKET_THROTTLE as u32;
+        peer.packetThrottleCounter = 0;
+        peer.packetThrottleEpoch = 0;
+        peer.lastRoundTripTime = peer.roundTripTime;
+        peer.lastRoundTripTimeVariance = peer.roundTripTimeVariance;
+        peer.lowestRoundTripTime = peer.roundTripTime;
+        peer.highestRoundTripTimeVariance = peer.roundTripTimeVariance;
+    }
+
     /// Forcefully disconnects this `Peer`.
     ///
     /// The foreign host represented by the peer is not notified of the disconnection and will timeout on its connection to the local host.
+    ///
+    /// Like with `disconnect_now`, no `Disconnect` event will be created, any `PeerID` referencing
+    /// this `Peer` will be invalid and all data associated with this `Peer` will be dropped. The
+    /// slot of the peer can then be reused by a new connection.
     pub fn reset(&mut self) {
+        // A disconnected peer was already cleaned up, or is cleaned up with its `Disconnect`
+        // event.
+        let disconnected = self.state() == PeerState::Disconnected;
         unsafe {
             enet_peer_reset(&mut self.inner as *mut _);
         }
+        if !disconnected {
+            self.cleanup_after_disconnect();
+        }
     }
 
     /// Returns the state this `Peer` is in.
@@ -202,25 +644,192 @@ where
     /// Queues a packet to be sent.
     ///
     /// Actual sending will happen during `Host::service`.
-    pub fn send_packet(&mut self, packet: Packet, channel_id: u8) -> Result<(), Error> {
-        let res =
-            unsafe { enet_peer_send(&mut self.inner as *mut _, channel_id, packet.into_inner()) };
+    ///
+    /// On success, ENet takes ownership of the packet and destroys it once it has been sent. If
+    /// the packet could not be queued, it is destroyed before this method returns.
+    ///
+    /// Packets larger than `Peer::max_packet_size` are rejected with `PeerSendError::TooLarge`.
+    pub fn send_packet(&mut self, packet: Packet, channel_id: u8) -> Result<(), PeerSendError> {
+        // If the packet was queued, dropping it leaves it to ENet.
+        self.send_shared(&packet, channel_id)
+    }
+
+    /// Queues a packet to be sent, without giving up ownership of it.
+    ///
+    /// If the packet was queued successfully, ENet references it, and destroys it once it was sent
+    /// to every peer it was queued for. Dropping the `Packet` then leaves it to ENet, so one packet
+    /// can be sent to several peers.
+    pub(crate) fn send_shared(
+        &mut self,
+        packet: &Packet,
+        channel_id: u8,
+    ) -> Result<(), PeerSendError> {
+        let len = packet.data().len();
+        let max = self.max_packet_size();
+        if len > max {
+            return Err(PeerSendError::TooLarge { len, max });
+        }
+
+        self.capture(Direction::Outgoing, channel_id, packet.data());
+
+        let res = unsafe { enet_peer_send(&mut self.inner as *mut _, channel_id, packet.as_raw()) };
 
         match res {
             r if r > 0 => panic!("unexpected res: {}", r),
-            0 => Ok(()),
-            r if r < 0 => Err(Error(r)),
+            0 => {
+                self.account_sent(channel_id, len);
+                Ok(())
+            }
+            r if r < 0 => Err(Error(r).into()),
             _ => panic!("unreachable"),
         }
     }
 
+    /// Queues `data` to be sent, without copying it.
+    ///
+    /// This is a shorthand for `Packet::new` followed by `Peer::send_packet`: the buffer of `data`
+    /// is handed to ENet directly, and dropped once ENet is done with the packet.
+    pub fn send_owned(
+        &mut self,
+        data: Vec<u8>,
+        mode: PacketMode,
+        channel_id: u8,
+    ) -> Result<(), PeerSendError> {
+        self.send_packet(Packet::new(data, mode)?, channel_id)
+    }
+
+    /// Queues `data` to be sent on `channel_id`, coalesced with other small messages.
+    ///
+    /// Queued messages are sent by `Peer::flush_queued`, as a single reliable sequenced packet per
+    /// channel, in which every message is prefixed by its length as a little-endian `u32`. The
+    /// receiver splits the packet with `CoalescedMessages`.
+    ///
+    /// If `data` doesn't fit into a single ENet packet together with the messages already queued
+    /// on `channel_id`, those are sent before `data` is queued. If sending them fails, they stay
+    /// queued and `data` isn't queued.
+    ///
+    /// Messages that are larger than `Peer::max_packet_size` together with their length prefix
+    /// are rejected with `PeerSendError::TooLarge`.
+    pub fn queue(&mut self, data: &[u8], channel_id: u8) -> Result<(), PeerSendError> {
+        let len = coalesce::framed_len(data);
+        let max = self.max_packet_size();
+        if len > max {
+            return Err(PeerSendError::TooLarge { len, max });
+        }
+
+        let max_payload_size = self.max_payload_size();
+        let queued = &mut self.raw_data_mut().queued;
+        let index = match queued
+            .iter()
+            .position(|(channel, _)| *channel == channel_id)
+        {
+            Some(index) => index,
+            None => {
+                queued.push((channel_id, Vec::new()));
+                queued.len() - 1
+            }
+        };
+
+        let buffer = &mut queued[index].1;
+        if !buffer.is_empty() && buffer.len() + len > max_payload_size {
+            let packet = Packet::new(std::mem::take(buffer), PacketMode::ReliableSequenced)?;
+            if let Err(err) = self.send_shared(&packet, channel_id) {
+                self.raw_data_mut().queued[index].1 = packet.data().to_vec();
+                return Err(err);
+            }
+        }
+
+        coalesce::write_frame(&mut self.raw_data_mut().queued[index].1, data);
+        Ok(())
+    }
+
+    /// Sets the priority of the messages queued on `channel_id` with `Peer::queue`.
+    ///
+    /// When `Peer::flush_queued` sends the queued messages, channels with a higher `weight` are
+    /// sent first, so on a congested link e.g. control messages leave before bulk data. All
+    /// channels start with a weight of 0.
+    ///
+    /// ENet itself sends the packets of a peer in the order they were queued, regardless of their
+    /// channel. The priority therefore only orders the packets of `Peer::flush_queued`, not those
+    /// sent with `Peer::send_packet`, or the packets `Peer::queue` sends early because they are
+    /// full.
+    pub fn set_channel_priority(&mut self, channel_id: u8, weight: u8) {
+        let priorities = &mut self.raw_data_mut().channel_priorities;
+        priorities.retain(|(channel, _)| *channel != channel_id);
+        if weight != 0 {
+            priorities.push((channel_id, weight));
+        }
+    }
+
+    /// Returns the priority of `channel_id`, see `Peer::set_channel_priority`.
+    pub fn channel_priority(&self, channel_id: u8) -> u8 {
+        self.raw_data()
+            .and_then(|peer_data| {
+                peer_data
+                    .channel_priorities
+                    .iter()
+                    .find(|(channel, _)| *channel == channel_id)
+            })
+            .map_or(0, |(_, weight)| *weight)
+    }
+
+    /// Sends all messages queued with `Peer::queue`, one packet per channel.
+    ///
+    /// The packets are queued in ENet like those of `Peer::send_packet`, and sent with the next
+    /// service (or `Host::flush`). If a packet can't be sent, the other channels are still
+    /// flushed, and the first error is returned.
+    ///
+    /// The channels are flushed in the order of their priority (see `Peer::set_channel_priority`),
+    /// channels of the same priority in the order they were first queued on.
+    pub fn flush_queued(&mut self) -> Result<(), PeerSendError> {
+        let mut queued = std::mem::take(&mut self.raw_data_mut().queued);
+        queued.sort_by_key(|(channel_id, _)| Reverse(self.channel_priority(*channel_id)));
+
+        let mut result = Ok(());
+        for (channel_id, buffer) in queued {
+            if buffer.is_empty() {
+                continue;
+            }
+
+            let res = self.send_owned(buffer, PacketMode::ReliableSequenced, channel_id);
+            if result.is_ok() {
+                result = res;
+            }
+        }
+
+        result
+    }
+
     /// Disconnects from this peer.
     ///
     /// A `Disconnect` event will be returned by `Host::service` once the disconnection is complete.
-    pub fn disconnect(&mut self, user_data: u32) {
+    /// The data associated with this `Peer` is dropped together with that event. To keep it, use
+    /// `disconnect_taking_data` instead, or take it out of the peer of the `Disconnect` event
+    /// (see `Event`).
+    ///
+    /// If the connection hasn't been established yet, ENet resets the peer right away instead, so
+    /// no `Disconnect` event is created and the data is dropped immediately.
+    ///
+    /// Fails without doing anything if the peer is already disconnecting or disconnected, so
+    /// disconnecting a peer twice is harmless.
+    pub fn disconnect(&mut self, user_data: u32) -> Result<(), DisconnectError> {
+        self.check_disconnectable()?;
         unsafe {
             enet_peer_disconnect(&mut self.inner as *mut _, user_data);
         }
+        self.cleanup_after_reset();
+        Ok(())
+    }
+
+    /// Disconnects from this peer, and takes the data associated with it out of it.
+    ///
+    /// Behaves like `disconnect`, but as the data is returned right away, it won't be dropped when
+    /// the `Disconnect` event is received later on.
+    pub fn disconnect_taking_data(&mut self, user_data: u32) -> Result<Option<T>, DisconnectError> {
+        self.check_disconnectable()?;
+        let data = self.take_data();
+        self.disconnect(user_data)?;
+        Ok(data)
     }
 
     /// Disconnects from this peer immediately.
@@ -231,41 +840,81 @@ where
     ///
     /// Any `PeerID` referencing this `Peer` will be invalid after this method is executed and all
     /// data associated with this `Peer` will be dropped.
-    pub fn disconnect_now(mut self, user_data: u32) {
+    ///
+    /// Unlike the other disconnect methods, this also cuts short a disconnect that is already in
+    /// progress. It only fails if the peer is already disconnected.
+    pub fn disconnect_now(mut self, user_data: u32) -> Result<(), DisconnectError> {
+        let state = self.state();
+        if state == PeerState::Disconnected {
+            return Err(DisconnectError::AlreadyDisconnecting(state));
+        }
+
         unsafe {
             enet_peer_disconnect_now(&mut self.inner as *mut _, user_data);
         }
         // Because no disconnect event is received, we have to clean up manually here.
         self.cleanup_after_disconnect();
+        Ok(())
     }
 
     /// Disconnects from this peer after all outgoing packets have been sent.
     ///
     /// A `Disconnect` event will be returned by `Host::service` once the disconnection is complete.
-    pub fn disconnect_later(&mut self, user_data: u32) {
+    ///
+    /// Fails in the same cases as `disconnect`. Calling this again while the outgoing packets are
+    /// still being sent only replaces `user_data`.
+    pub fn disconnect_later(&mut self, user_data: u32) -> Result<(), DisconnectError> {
+        self.check_disconnectable()?;
         unsafe {
             enet_peer_disconnect_later(&mut self.inner as *mut _, user_data);
         }
+        self.cleanup_after_reset();
+        Ok(())
+    }
+
+    /// Returns an error if this peer is in a state in which ENet ignores disconnects.
+    fn check_disconnectable(&self) -> Result<(), DisconnectError> {
+        match self.state() {
+            state @ (PeerState::Disconnected
+            | PeerState::Disconnecting
+            | PeerState::AcknowledgingDisconnect
+            | PeerState::Zombie) => Err(DisconnectError::AlreadyDisconnecting(state)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Cleans up after a disconnect, if ENet reset this peer instead of disconnecting gracefully.
+    fn cleanup_after_reset(&mut self) {
+        if self.state() == PeerState::Disconnected {
+            self.cleanup_after_disconnect();
+        }
     }
 
     /// Attempts to dequeue an incoming packet from this `Peer`.
     ///
     /// On success, returns the packet and the channel id of the receiving channel.
     pub fn receive(&mut self) -> Option<PeerPacket> {
-        let mut channel_id = 0u8;
-        let res =
-            unsafe { enet_peer_receive(&mut self.inner as *mut _, &mut channel_id as *mut _) };
-        if res.is_null() {
-            None
-        } else {
-            Some(PeerPacket {
-                packet: Packet::from_sys_packet(res),
-                channel_id,
-            })
+        loop {
+            let mut channel_id = 0u8;
+            let res =
+                unsafe { enet_peer_receive(&mut self.inner as *mut _, &mut channel_id as *mut _) };
+            if res.is_null() {
+                return None;
+            }
+
+            // Packets rejected by the filter are dropped here.
+            let packet = Packet::from_sys_packet(res);
+            if self.accept_incoming(channel_id, packet.data()) {
+                return Some(PeerPacket { packet, channel_id });
+            }
         }
     }
 }
 
+fn duration_to_millis(duration: Duration) -> u32 {
+    duration.as_millis().try_into().unwrap_or(u32::MAX)
+}
+
 impl<T> Debug for Peer<T>
 where
     T: Debug,
@@ -275,6 +924,34 @@ where
     }
 }
 
+/// Helpers for hosts whose peers store data of different types, e.g. `Host<Box<dyn Any>>`.
+impl Peer<Box<dyn Any>> {
+    /// Returns a reference to the data associated with this `Peer`, if it is set and of type `U`.
+    ///
+    /// ```no_run
+    /// # use std::any::Any;
+    /// # use enet::Peer;
+    /// struct User { name: String }
+    /// struct Anonymous;
+    ///
+    /// fn greeting(peer: &Peer<Box<dyn Any>>) -> String {
+    ///     match peer.downcast_data::<User>() {
+    ///         Some(user) => format!("Hello, {}!", user.name),
+    ///         None => "Hello, stranger!".to_string(),
+    ///     }
+    /// }
+    /// ```
+    pub fn downcast_data<U: 'static>(&self) -> Option<&U> {
+        self.data()?.downcast_ref()
+    }
+
+    /// Returns a mutable reference to the data associated with this `Peer`, if it is set and of
+    /// type `U`.
+    pub fn downcast_data_mut<U: 'static>(&mut self) -> Option<&mut U> {
+        self.data_mut()?.downcast_mut()
+    }
+}
+
 /// The ID of a [Peer](struct.Peer.html).
 ///
 /// Can be used with the [peer](struct.Host.html#method.peer)/[peer_mut](struct.Host.html#method.peer_mut)-methods of Host, to retrieve references to a Peer.
@@ -288,6 +965,76 @@ pub struct PeerID {
     pub generation: usize,
 }
 
+/// Timeout parameters of a `Peer` (see `Peer::set_timeout`).
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct PeerTimeout {
+    /// A factor that, multiplied with a value based on the average round trip time, determines
+    /// the timeout limit for a reliable packet. ENet's default is 32.
+    pub limit: u32,
+    /// A reliable packet is never considered timed out before it has been unacknowledged for this
+    /// long. ENet's default is 5 seconds.
+    pub minimum: Duration,
+    /// A reliable packet is always considered timed out after it has been unacknowledged for this
+    /// long. ENet's default is 30 seconds.
+    pub maximum: Duration,
+}
+
+/// Throttle parameters of a `Peer` (see `Peer::configure_throttle`).
+///
+/// ENet measures the round trip time over each `interval`. If it was lower than the mean, the
+/// throttle is increased by `acceleration`, if it was higher, the throttle is decreased by
+/// `deceleration`, where the throttle ranges from 0 to 32.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct PeerThrottle {
+    /// The interval over which the round trip time is measured. ENet's default is 5 seconds.
+    pub interval: Duration,
+    /// The rate at which the throttle increases. ENet's default is 2.
+    pub acceleration: u32,
+    /// The rate at which the throttle decreases. ENet's default is 2.
+    pub deceleration: u32,
+}
+
+/// Settings that are applied to a `Peer` right after it was created by `Host::connect_with`.
+///
+/// Settings that are `None` keep ENet's defaults.
+#[derive(Debug, Clone, Copy, Default, Hash, PartialEq, Eq)]
+pub struct PeerConfig {
+    /// See `Peer::set_timeout`.
+    pub timeout: Option<PeerTimeout>,
+    /// See `Peer::set_ping_interval`.
+    pub ping_interval: Option<Duration>,
+    /// See `Peer::configure_throttle`.
+    pub throttle: Option<PeerThrottle>,
+}
+
+/// Returns the sum of the fragment lengths of a list of `ENetOutgoingCommand`s.
+///
+/// `list` has to be a valid list of outgoing commands.
+unsafe fn outgoing_data_len(list: &ENetList) -> usize {
+    list_commands(list)
+        .map(|command| usize::from(command.fragmentLength))
+        .sum()
+}
+
+/// Returns an iterator over a list of `ENetOutgoingCommand`s.
+///
+/// `list` has to be a valid list of outgoing commands, which isn't modified while iterating.
+unsafe fn list_commands(list: &ENetList) -> impl Iterator<Item = &ENetOutgoingCommand> {
+    let sentinel = &list.sentinel as *const ENetListNode;
+    let mut node = list.sentinel.next as *const ENetListNode;
+
+    std::iter::from_fn(move || {
+        if std::ptr::eq(node, sentinel) {
+            return None;
+        }
+
+        // `outgoingCommandList` is the first field of a command, so the node is the command itself.
+        let command = unsafe { &*(node as *const ENetOutgoingCommand) };
+        node = unsafe { (*node).next };
+        Some(command)
+    })
+}
+
 /// Describes the state a `Peer` is in.
 ///
 /// The states should be self-explanatory, ENet doesn't explain them more
@@ -327,3 +1074,442 @@ impl PeerState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{any::Any, net::Ipv4Addr, time::Duration};
+
+    use super::{PeerConfig, PeerThrottle, PeerTimeout};
+    use crate::{
+        test_util::{connect, localhost_host, service_until},
+        tests::{allocated, connected_pair, CONNECT_DATA, ENET},
+        Address, BandwidthLimit, ChannelLimit, CoalescedMessages, DisconnectError, EventType,
+        PacketMode, PeerSendError, PeerState,
+    };
+
+    #[test]
+    fn test_disconnect_taking_data() {
+        let (mut server, _, mut client, server_id) = connected_pair::<String>();
+
+        let peer = client.peer_mut(server_id).unwrap();
+        peer.set_data("session".to_string());
+        assert_eq!(
+            peer.disconnect_taking_data(42),
+            Ok(Some("session".to_string()))
+        );
+        assert_eq!(peer.data(), None);
+
+        service_until(&mut [&mut server, &mut client], |host, event| {
+            (host == 1 && matches!(event.r#type(), EventType::Disconnect { .. })).then_some(())
+        });
+
+        assert!(client.peer(server_id).is_none());
+    }
+
+    #[test]
+    fn test_double_disconnect() {
+        let (_server, _, mut client, server_id) = connected_pair::<String>();
+
+        let peer = client.peer_mut(server_id).unwrap();
+        peer.set_data("session".to_string());
+        assert_eq!(peer.disconnect(1), Ok(()));
+
+        let disconnecting = Err(DisconnectError::AlreadyDisconnecting(
+            PeerState::Disconnecting,
+        ));
+        assert_eq!(peer.disconnect(2), disconnecting);
+        assert_eq!(peer.disconnect_later(2), disconnecting);
+        assert_eq!(peer.disconnect_taking_data(2), disconnecting.map(|()| None));
+        assert_eq!(peer.data(), Some(&"session".to_string()));
+    }
+
+    #[test]
+    fn test_disconnect_after_reset() {
+        let (_server, _, mut client, server_id) = connected_pair::<()>();
+
+        let peer = client.peer_mut(server_id).unwrap();
+        peer.reset();
+
+        let disconnected = Err(DisconnectError::AlreadyDisconnecting(
+            PeerState::Disconnected,
+        ));
+        assert_eq!(peer.disconnect(0), disconnected);
+        assert_eq!(peer.disconnect_later(0), disconnected);
+        assert_eq!(peer.state(), PeerState::Disconnected);
+    }
+
+    #[test]
+    fn test_disconnect_while_connecting() {
+        let mut client = ENET
+            .create_host::<String>(
+                None,
+                1,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+            .unwrap();
+
+        // Nothing is listening on this address, so the connection is never established.
+        let address = Address::new(Ipv4Addr::LOCALHOST, 9);
+        let (peer, peer_id) = client.connect(&address, 1, 0).unwrap();
+        peer.set_data("session".to_string());
+        assert_eq!(peer.disconnect(0), Ok(()));
+
+        // ENet resets connecting peers right away, without a `Disconnect` event.
+        assert!(client.peer(peer_id).is_none());
+        assert!(client.peers().all(|peer| peer.data().is_none()));
+    }
+
+    #[test]
+    fn test_reset_statistics() {
+        let (_server, _, mut client, server_id) = connected_pair::<()>();
+
+        let peer = client.peer_mut(server_id).unwrap();
+        unsafe {
+            let raw = &mut *peer.as_raw_mut();
+            raw.packetsSent = 100;
+            raw.packetsLost = 20;
+            raw.packetLoss = 1000;
+            raw.packetThrottle = 4;
+            raw.packetThrottleEpoch = 1;
+        }
+
+        peer.reset_statistics();
+        let raw = unsafe { &*peer.as_raw() };
+        assert_eq!(
+            (raw.packetsSent, raw.packetsLost, raw.packetLoss),
+            (0, 0, 0)
+        );
+        assert_eq!(raw.packetThrottleEpoch, 0);
+        assert_eq!(peer.packet_throttle(), 32);
+        assert_eq!(raw.lowestRoundTripTime, raw.roundTripTime);
+    }
+
+    #[test]
+    fn test_reliable_windows() {
+        let (_server, _, client, server_id) = connected_pair::<()>();
+
+        let peer = client.peer(server_id).unwrap();
+        assert!(peer.reliable_window() >= 4096);
+        assert!(peer.packet_throttle() <= 32);
+        assert_eq!(peer.used_reliable_windows(1), Some(0));
+        assert_eq!(peer.reliable_windows(1), Some([0; 16]));
+        assert_eq!(peer.used_reliable_windows(2), None);
+        assert_eq!(peer.reliable_windows(2), None);
+    }
+
+    #[test]
+    fn test_host_address() {
+        let (mut server, client_id, client, server_id) = connected_pair::<()>();
+
+        let server_address = server.address();
+        assert_eq!(
+            server.peer_mut(client_id).unwrap().host_address(),
+            server_address
+        );
+        assert_eq!(
+            client.peer(server_id).unwrap().host_address(),
+            client.address()
+        );
+    }
+
+    #[test]
+    fn test_replace_data() {
+        let (mut server, client_id, _client, _) = connected_pair::<String>();
+
+        let peer = server.peer_mut(client_id).unwrap();
+        assert_eq!(peer.replace_data("first".to_string()), None);
+        assert_eq!(
+            peer.replace_data("second".to_string()),
+            Some("first".to_string())
+        );
+        assert_eq!(peer.data(), Some(&"second".to_string()));
+    }
+
+    #[test]
+    fn test_downcast_data() {
+        let (mut server, client_id, _client, _) = connected_pair::<Box<dyn Any>>();
+
+        let peer = server.peer_mut(client_id).unwrap();
+        assert_eq!(peer.downcast_data::<u32>(), None);
+
+        peer.set_data(Box::new(5_u32));
+        assert_eq!(peer.downcast_data::<String>(), None);
+        *peer.downcast_data_mut::<u32>().unwrap() += 1;
+        assert_eq!(peer.downcast_data::<u32>(), Some(&6));
+
+        peer.set_data(Box::new("anonymous".to_string()));
+        assert_eq!(peer.downcast_data::<u32>(), None);
+        assert_eq!(
+            peer.downcast_data::<String>().map(String::as_str),
+            Some("anonymous")
+        );
+    }
+
+    #[test]
+    fn test_set_data_with() {
+        let (mut server, client_id, _client, _) = connected_pair::<(Address, u32)>();
+
+        let peer = server.peer_mut(client_id).unwrap();
+        assert_eq!(peer.connect_data(), CONNECT_DATA);
+
+        peer.set_data_with(|address, data| (address, data));
+        let (address, data) = peer.data().unwrap();
+        assert_eq!(*address.ip(), Ipv4Addr::LOCALHOST);
+        assert_eq!(*data, CONNECT_DATA);
+    }
+
+    #[test]
+    fn test_connect_with_config() {
+        let server = localhost_host::<()>(1);
+        let mut client = ENET
+            .create_host::<()>(
+                None,
+                1,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+            .unwrap();
+
+        let config = PeerConfig {
+            timeout: Some(PeerTimeout {
+                limit: 64,
+                minimum: Duration::from_secs(10),
+                maximum: Duration::from_secs(60),
+            }),
+            ping_interval: Some(Duration::from_secs(1)),
+            throttle: Some(PeerThrottle {
+                interval: Duration::from_secs(2),
+                acceleration: 3,
+                deceleration: 4,
+            }),
+        };
+        let (peer, _) = client
+            .connect_with(&server.address(), 1, 0, &config)
+            .unwrap();
+
+        let inner = unsafe { &*peer.as_raw() };
+        assert_eq!(inner.timeoutLimit, 64);
+        assert_eq!(inner.timeoutMinimum, 10_000);
+        assert_eq!(inner.timeoutMaximum, 60_000);
+        assert_eq!(inner.pingInterval, 1000);
+        assert_eq!(inner.packetThrottleInterval, 2000);
+        assert_eq!(inner.packetThrottleAcceleration, 3);
+        assert_eq!(inner.packetThrottleDeceleration, 4);
+    }
+
+    #[test]
+    fn test_send_owned_frees_data() {
+        const SIZE: usize = 1 << 20;
+
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+
+        // The first buffer is received and dropped, the second is still queued when the host is
+        // destroyed.
+        let before = allocated();
+        let peer = client.peer_mut(server_id).unwrap();
+        peer.send_owned(vec![1; SIZE], PacketMode::ReliableSequenced, 0)
+            .unwrap();
+        client.flush();
+        let peer = client.peer_mut(server_id).unwrap();
+        peer.send_owned(vec![2; SIZE], PacketMode::ReliableSequenced, 0)
+            .unwrap();
+        assert!(allocated() - before >= 2 * SIZE as isize);
+
+        let packet = service_until(&mut [&mut client, &mut server], |_, event| {
+            event.take_packet()
+        });
+        assert_eq!(packet.data().len(), SIZE);
+        drop(packet);
+
+        drop(client);
+        assert!(allocated() - before < SIZE as isize);
+    }
+
+    #[test]
+    fn test_send_queue_saturated() {
+        let (_server, _, mut client, server_id) = connected_pair::<()>();
+
+        let peer = client.peer_mut(server_id).unwrap();
+        assert!(!peer.is_send_queue_saturated());
+
+        // Far more than the largest possible window of 64KiB.
+        for _ in 0..200 {
+            peer.send_owned(vec![0; 1000], PacketMode::ReliableSequenced, 0)
+                .unwrap();
+        }
+        client.flush();
+
+        assert!(client.is_send_queue_saturated(server_id));
+    }
+
+    #[test]
+    fn test_max_payload_size() {
+        let (_server, _, client, server_id) = connected_pair::<()>();
+
+        let peer = client.peer(server_id).unwrap();
+        // ENet's default MTU.
+        assert_eq!(peer.mtu(), 1392);
+        assert_eq!(peer.max_payload_size(), 1364);
+
+        client.set_checksum_crc32();
+        assert_eq!(client.peer(server_id).unwrap().max_payload_size(), 1360);
+    }
+
+    #[test]
+    fn test_send_too_large() {
+        let (_server, _, mut client, server_id) = connected_pair::<()>();
+
+        assert_eq!(client.maximum_packet_size(), 32 * 1024 * 1024);
+        client.set_maximum_packet_size(1000);
+        assert_eq!(client.peer(server_id).unwrap().max_packet_size(), 1000);
+
+        let before = allocated();
+        let peer = client.peer_mut(server_id).unwrap();
+        match peer.send_owned(vec![0; 1001], PacketMode::ReliableSequenced, 0) {
+            Err(PeerSendError::TooLarge { len, max }) => assert_eq!((len, max), (1001, 1000)),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(allocated(), before);
+
+        peer.send_owned(vec![0; 1000], PacketMode::ReliableSequenced, 0)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_queue_coalesces_messages() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+
+        let peer = client.peer_mut(server_id).unwrap();
+        let max_payload_size = peer.max_payload_size();
+        peer.queue(b"first", 0).unwrap();
+        peer.queue(b"", 0).unwrap();
+        peer.queue(b"second", 0).unwrap();
+        // Doesn't fit into the first packet anymore.
+        let large = vec![3; max_payload_size - 4];
+        peer.queue(&large, 0).unwrap();
+        peer.flush_queued().unwrap();
+        // Nothing is queued anymore.
+        peer.flush_queued().unwrap();
+        client.flush();
+
+        let packets: Vec<_> = (0..2)
+            .map(|_| {
+                service_until(&mut [&mut client, &mut server], |_, event| {
+                    event.take_packet().map(|packet| packet.data().to_vec())
+                })
+            })
+            .collect();
+
+        let split = |packet: &Vec<u8>| -> Vec<Vec<u8>> {
+            CoalescedMessages::new(packet)
+                .map(|message| message.unwrap().to_vec())
+                .collect()
+        };
+        assert_eq!(
+            split(&packets[0]),
+            [b"first".to_vec(), Vec::new(), b"second".to_vec()]
+        );
+        assert_eq!(split(&packets[1]), [large]);
+        assert_eq!(packets[1].len(), max_payload_size);
+    }
+
+    #[test]
+    fn test_queue_errors() {
+        let mut server = localhost_host::<()>(1);
+        let mut client = localhost_host::<()>(1);
+        client.set_maximum_packet_size(1000);
+        let (_, server_id) = client.connect(&server.address(), 1, 0).unwrap();
+
+        // The length prefix counts towards the packet size.
+        let peer = client.peer_mut(server_id).unwrap();
+        match peer.queue(&[0; 997], 0) {
+            Err(PeerSendError::TooLarge { len, max }) => assert_eq!((len, max), (1001, 1000)),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // ENet doesn't send to a peer that is still connecting, so the full packet stays queued.
+        client.set_maximum_packet_size(32 * 1024 * 1024);
+        let peer = client.peer_mut(server_id).unwrap();
+        peer.queue(b"first", 0).unwrap();
+        let large = vec![1; peer.max_payload_size() - 4];
+        match peer.queue(&large, 0) {
+            Err(PeerSendError::Enet(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        service_until(&mut [&mut server, &mut client], |host, event| {
+            (host == 1 && matches!(event.r#type(), EventType::Connect { .. })).then_some(())
+        });
+        client.peer_mut(server_id).unwrap().flush_queued().unwrap();
+        client.flush();
+
+        let packet = service_until(&mut [&mut server], |_, event| event.take_packet());
+        let messages: Vec<_> = CoalescedMessages::new(packet.data())
+            .map(|message| message.unwrap().to_vec())
+            .collect();
+        assert_eq!(messages, [b"first".to_vec()]);
+    }
+
+    #[test]
+    fn test_channel_priority() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+
+        let peer = client.peer_mut(server_id).unwrap();
+        assert_eq!(peer.channel_priority(0), 0);
+        peer.set_channel_priority(0, 10);
+        assert_eq!(peer.channel_priority(0), 10);
+
+        // Bulk data is queued first, but the control channel is flushed first.
+        peer.queue(b"bulk", 1).unwrap();
+        peer.queue(b"control", 0).unwrap();
+        peer.flush_queued().unwrap();
+        // Equal priorities keep the order of the queue.
+        peer.set_channel_priority(0, 0);
+        peer.queue(b"bulk", 1).unwrap();
+        peer.queue(b"control", 0).unwrap();
+        peer.flush_queued().unwrap();
+        client.flush();
+
+        let channels: Vec<_> = (0..4)
+            .map(|_| {
+                service_until(&mut [&mut client, &mut server], |_, event| {
+                    match event.r#type() {
+                        EventType::Receive { channel_id, .. } => Some(*channel_id),
+                        _ => None,
+                    }
+                })
+            })
+            .collect();
+        assert_eq!(channels, [0, 1, 1, 0]);
+    }
+
+    #[test]
+    fn test_queue_after_reset() {
+        let (mut server, _, mut client, server_id) = connected_pair::<()>();
+
+        let peer = client.peer_mut(server_id).unwrap();
+        peer.queue(b"stale", 0).unwrap();
+        peer.set_channel_priority(1, 5);
+        peer.reset();
+
+        // The client has a single slot, so the new connection reuses it.
+        let (client_id, server_id) = connect(&mut server, &mut client, 0);
+        let peer = client.peer_mut(server_id).unwrap();
+        assert_eq!(peer.channel_priority(1), 0);
+        peer.flush_queued().unwrap();
+        peer.queue(b"fresh", 0).unwrap();
+        peer.flush_queued().unwrap();
+        client.flush();
+
+        let packet = service_until(&mut [&mut server], |_, event| {
+            (event.peer_id() == client_id).then(|| event.take_packet())?
+        });
+        let messages: Vec<_> = CoalescedMessages::new(packet.data())
+            .map(|message| message.unwrap().to_vec())
+            .collect();
+        assert_eq!(messages, [b"fresh".to_vec()]);
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{Error, Event, Host};
+
+/// Services a `Host` until it is told to stop, and then shuts it down gracefully.
+///
+/// This packages the usual run loop of a server thread: events are passed to a handler until the
+/// `stop` flag is set, after which all peers are disconnected with `Host::shutdown`.
+///
+/// As a `Host` can't be sent to another thread, the `ServiceLoop` has to be created on the thread
+/// that runs it, only the `stop` flag is shared.
+///
+/// ```no_run
+/// # use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, thread};
+/// # use enet::*;
+/// let stop = Arc::new(AtomicBool::new(false));
+///
+/// let server_stop = stop.clone();
+/// let server = thread::spawn(move || -> Result<(), Error> {
+///     let enet = Enet::new().unwrap();
+///     let host = enet
+///         .create_host::<()>(
+///             Some(&"127.0.0.1:9001".parse().unwrap()),
+///             32,
+///             ChannelLimit::Maximum,
+///             BandwidthLimit::Unlimited,
+///             BandwidthLimit::Unlimited,
+///         )
+///         .unwrap();
+///
+///     ServiceLoop::new(host, server_stop).run(|event| println!("{:?}", event.r#type()))?;
+///     Ok(())
+/// });
+///
+/// // ...
+/// stop.store(true, Ordering::Release);
+/// server.join().unwrap().unwrap();
+/// ```
+pub struct ServiceLoop<T> {
+    host: Host<T>,
+    stop: Arc<AtomicBool>,
+    service_timeout: Duration,
+    disconnect_data: u32,
+    shutdown_timeout: Duration,
+}
+
+impl<T> ServiceLoop<T> {
+    /// Creates a `ServiceLoop` that services `host` until `stop` is set.
+    ///
+    /// By default, the host is serviced with a timeout of 10ms, so the loop notices `stop` within
+    /// that time, and the shutdown waits up to 3s for the peers to acknowledge a disconnect with
+    /// `user_data` 0.
+    pub fn new(host: Host<T>, stop: Arc<AtomicBool>) -> ServiceLoop<T> {
+        ServiceLoop {
+            host,
+            stop,
+            service_timeout: Duration::from_millis(10),
+            disconnect_data: 0,
+            shutdown_timeout: Duration::from_secs(3),
+        }
+    }
+
+    /// Returns a reference to the `Host` of this loop.
+    pub fn host(&self) -> &Host<T> {
+        &self.host
+    }
+
+    /// Returns a mutable reference to the `Host` of this loop.
+    pub fn host_mut(&mut self) -> &mut Host<T> {
+        &mut self.host
+    }
+
+    /// Sets the timeout every service of the host waits for events.
+    ///
+    /// This is also the maximum delay until the loop notices that it should stop.
+    pub fn set_service_timeout(&mut self, timeout: Duration) {
+        self.service_timeout = timeout;
+    }
+
+    /// Sets the `user_data` the peers are disconnected with, and how long the shutdown waits
+    /// for them to acknowledge the disconnect (see `Host::shutdown`).
+    pub fn set_shutdown(&mut self, user_data: u32, timeout: Duration) {
+        self.disconnect_data = user_data;
+        self.shutdown_timeout = timeout;
+    }
+
+    /// Services the host and passes all events to `f` until `stop` is set, then shuts the host
+    /// down.
+    ///
+    /// Returns the host once all peers are disconnected.
+    pub fn run(mut self, mut f: impl FnMut(Event<'_, T>)) -> Result<Host<T>, Error> {
+        while !self.stop.load(Ordering::Acquire) {
+            self.host.for_each_event(self.service_timeout, &mut f)?;
+        }
+
+        self.host
+            .shutdown(self.disconnect_data, self.shutdown_timeout)?;
+        Ok(self.host)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::Ipv4Addr,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc, Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use super::ServiceLoop;
+    use crate::{
+        test_util::wait_for, tests::ENET, Address, BandwidthLimit, ChannelLimit, EventType,
+        PeerState,
+    };
+
+    #[test]
+    fn test_run_until_stopped() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (port_sender, port_receiver) = mpsc::channel();
+        let (connect_sender, connect_receiver) = mpsc::channel();
+
+        let server_stop = stop.clone();
+        let server_thread = thread::spawn(move || {
+            let host = ENET
+                .create_host::<()>(
+                    Some(&Address::new(Ipv4Addr::LOCALHOST, 0)),
+                    1,
+                    ChannelLimit::Maximum,
+                    BandwidthLimit::Unlimited,
+                    BandwidthLimit::Unlimited,
+                )
+                .unwrap();
+            port_sender.send(host.address().port()).unwrap();
+
+            let mut service_loop = ServiceLoop::new(host, server_stop);
+            service_loop.set_shutdown(7, Duration::from_secs(5));
+
+            let host = service_loop
+                .run(|event| {
+                    if let EventType::Connect { .. } = event.r#type() {
+                        connect_sender.send(()).unwrap();
+                    }
+                })
+                .unwrap();
+
+            assert!(host
+                .peers()
+                .all(|peer| peer.state() == PeerState::Disconnected));
+        });
+
+        let mut client = ENET
+            .create_host::<()>(
+                None,
+                1,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+            .unwrap();
+        let server = Address::new(Ipv4Addr::LOCALHOST, port_receiver.recv().unwrap());
+        client.connect(&server, 1, 0).unwrap();
+
+        let mut disconnect_data = None;
+        wait_for("shutdown", || {
+            if connect_receiver.try_recv().is_ok() {
+                stop.store(true, Ordering::Release);
+            }
+            if let Some(event) = client.service(Duration::from_millis(10)).unwrap() {
+                if let EventType::Disconnect { data } = event.r#type() {
+                    disconnect_data = Some(*data);
+                }
+            }
+            server_thread.is_finished().then_some(())
+        });
+
+        server_thread.join().unwrap();
+        assert_eq!(disconnect_data, Some(7));
+    }
+}
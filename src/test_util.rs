@@ -0,0 +1,177 @@
+//! Utilities for testing code that uses ENet.
+//!
+//! Available with the `test-util` feature.
+//!
+//! Tests run in parallel threads of the same process, so `enet` returns a shared instance
+//! instead of calling `Enet::new` in every test. The instance is never dropped, so ENet stays
+//! initialized for all tests, instead of being deinitialized and initialized again whenever no
+//! test happens to hold an `Enet`.
+//!
+//! A `Host` and its peers are destroyed by the `Drop` impl of the `Host`, which also runs while a
+//! failing test unwinds, so a panic doesn't leak sockets or peers into later tests.
+
+use std::{
+    net::Ipv4Addr,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use crate::{Address, BandwidthLimit, ChannelLimit, Enet, Event, EventType, Host, PeerID};
+
+/// How long the helpers of this module wait before they give up.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns the `Enet` instance shared by all tests of this process.
+///
+/// # Panics
+/// Panics if ENet fails to initialize.
+pub fn enet() -> Enet {
+    static ENET: OnceLock<Enet> = OnceLock::new();
+
+    ENET.get_or_init(|| Enet::new().expect("failed to initialize ENet"))
+        .clone()
+}
+
+/// Creates a `Host` listening on a free port of localhost, with all channels and no bandwidth
+/// limits.
+///
+/// The port can be found out via `Host::address`.
+pub fn localhost_host<T>(max_peer_count: usize) -> Host<T> {
+    enet()
+        .create_host(
+            Some(&Address::new(Ipv4Addr::LOCALHOST, 0)),
+            max_peer_count,
+            ChannelLimit::Maximum,
+            BandwidthLimit::Unlimited,
+            BandwidthLimit::Unlimited,
+        )
+        .unwrap()
+}
+
+/// Calls `step` until it returns `Some`, and returns its value.
+///
+/// `step` should block for a short time, e.g. by servicing a `Host` with a timeout, instead of
+/// returning right away.
+///
+/// # Panics
+/// Panics with "`what` timed out" if `step` doesn't return `Some` within 5 seconds.
+pub fn wait_for<R>(what: &str, mut step: impl FnMut() -> Option<R>) -> R {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let Some(res) = step() {
+            return res;
+        }
+        assert!(Instant::now() < deadline, "{} timed out", what);
+    }
+}
+
+/// Services `hosts` in turn, until `f` returns `Some` for one of their events, and returns its
+/// value.
+///
+/// `f` receives the index of the serviced host in `hosts` and the event. Every host is serviced
+/// for up to 10ms at a time, so the other hosts keep sending and acknowledging data.
+///
+/// # Panics
+/// Panics if servicing fails, or if `f` doesn't return `Some` within 5 seconds.
+pub fn service_until<T, R>(
+    hosts: &mut [&mut Host<T>],
+    mut f: impl FnMut(usize, Event<'_, T>) -> Option<R>,
+) -> R {
+    wait_for("servicing", || {
+        for (index, host) in hosts.iter_mut().enumerate() {
+            if let Some(event) = host.service(Duration::from_millis(10)).unwrap() {
+                if let Some(res) = f(index, event) {
+                    return Some(res);
+                }
+            }
+        }
+        None
+    })
+}
+
+/// Connects `client` to `server` with 2 channels and `connect_data`, and services both until they
+/// received their `Connect` event.
+///
+/// Returns the `PeerID` of the client on the server and the `PeerID` of the server on the client.
+/// Other events are dropped.
+///
+/// # Panics
+/// Panics if the hosts don't connect within 5 seconds.
+pub fn connect<T>(
+    server: &mut Host<T>,
+    client: &mut Host<T>,
+    connect_data: u32,
+) -> (PeerID, PeerID) {
+    let (_, server_id) = client.connect(&server.address(), 2, connect_data).unwrap();
+
+    let mut client_id = None;
+    let mut client_connected = false;
+    service_until(&mut [server, client], |index, event| {
+        if let EventType::Connect { .. } = event.r#type() {
+            match index {
+                0 => client_id = Some(event.peer_id()),
+                _ => client_connected = true,
+            }
+        }
+        client_id.filter(|_| client_connected)
+    });
+
+    (client_id.unwrap(), server_id)
+}
+
+/// Creates a server and a client `Host` on localhost and connects them, the client with
+/// `connect_data`.
+///
+/// The server has room for 4 peers and the client for 1, the connection uses 2 channels.
+///
+/// # Panics
+/// Panics if the hosts can't be created or don't connect within 5 seconds.
+pub fn connected_pair<T>(connect_data: u32) -> (Host<T>, PeerID, Host<T>, PeerID) {
+    let mut server = localhost_host(4);
+    let mut client = enet()
+        .create_host::<T>(
+            None,
+            1,
+            ChannelLimit::Maximum,
+            BandwidthLimit::Unlimited,
+            BandwidthLimit::Unlimited,
+        )
+        .unwrap();
+
+    let (client_id, server_id) = connect(&mut server, &mut client, connect_data);
+    (server, client_id, client, server_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, panic};
+
+    use super::{connected_pair, enet};
+    use crate::{Address, BandwidthLimit, ChannelLimit, PeerState};
+
+    #[test]
+    fn test_host_destroyed_on_panic() {
+        let address = panic::catch_unwind(|| {
+            let (server, _, _client, _) = connected_pair::<()>(0);
+            assert!(server
+                .peers()
+                .any(|peer| peer.state() == PeerState::Connected));
+            let address = server.address();
+            panic::panic_any(address);
+        })
+        .unwrap_err()
+        .downcast::<Address>()
+        .unwrap();
+
+        // The socket of the server was closed while unwinding.
+        enet()
+            .create_host::<()>(
+                Some(&Address::new(Ipv4Addr::LOCALHOST, address.port())),
+                1,
+                ChannelLimit::Maximum,
+                BandwidthLimit::Unlimited,
+                BandwidthLimit::Unlimited,
+            )
+            .unwrap();
+    }
+}